@@ -1,108 +1,264 @@
 #[macro_use]
 extern crate criterion;
-extern crate sdl;
 
-use mountain::{camera, renderer, terrain};
+use mountain::camera::Camera;
+use mountain::renderer::Renderer;
+use mountain::terrain;
+use mountain::terrain::{HeightMap, Texture};
 
 use criterion::{black_box, Criterion};
-use mountain::config::RendererConfig;
-use sdl::video::SurfaceFlag;
+use mountain::config::{Dithering, GeneratorConfig, RendererConfig};
+use mountain::framebuffer::{Framebuffer, MemoryFramebuffer, PixelFormat};
+use mountain::sky::Sky;
+use mountain::terrain::lighting::{Lightmap, Sun};
 use std::time::Duration;
 
+/// What the frames are drawn from
+#[derive(Clone, Copy)]
+struct Terrain<'a> {
+    map: &'a HeightMap,
+    texture: &'a Texture,
+    lightmap: Option<&'a Lightmap>,
+}
+
+/// Measure the drawing of a frame, after reporting the steps it takes in z: they show how much of
+/// the distance the renderer had to go through
+fn bench(
+    c: &mut Criterion,
+    name: &str,
+    screen: &mut MemoryFramebuffer,
+    terrain: &Terrain,
+    camera: &Camera,
+    config: &RendererConfig,
+) {
+    let mut renderer = Renderer::new(Sky::default());
+    let mut draw = |screen: &mut MemoryFramebuffer| {
+        renderer.draw(
+            screen,
+            terrain.map,
+            terrain.texture,
+            terrain.lightmap,
+            camera,
+            config,
+        )
+    };
+    println!("{}: {} steps in z", name, draw(screen));
+    c.bench_function(name, |b| b.iter(|| draw(black_box(screen))));
+}
+
 // TODO: Refactor for slow/fast processor
 pub fn draw_bench(c: &mut Criterion) {
     let bench_config_fast = RendererConfig {
         fog_start: 300,
-        fog: true,
         distance_max: 350,
         enable_filtering: false,
         scale_height: 1.0,
+        ..RendererConfig::default()
     };
 
     let bench_config_slow = RendererConfig {
         fog_start: 1000,
-        fog: true,
         distance_max: 1100,
-        enable_filtering: true,
         scale_height: 1.0,
+        ..RendererConfig::default()
+    };
+
+    let bench_config_slow_lod = RendererConfig {
+        enable_lod: true,
+        lod_distance: 250,
+        ..bench_config_slow
+    };
+
+    let bench_config_fast_integer = RendererConfig {
+        integer_only: true,
+        ..bench_config_fast
+    };
+
+    let bench_config_slow_integer = RendererConfig {
+        integer_only: true,
+        ..bench_config_slow
+    };
+
+    let bench_config_fast_dithered = RendererConfig {
+        dithering: Dithering::BlueNoise,
+        ..bench_config_fast
     };
 
-    let map = match terrain::HeightMap::from_file("hm.png") {
-        Err(e) => {
-            println!("Cannot open the map: {}", e);
-            return;
-        }
-        Ok(im) => im,
+    let bench_config_slow_parallel = RendererConfig {
+        threads: 4,
+        ..bench_config_slow
     };
 
-    let texture = match terrain::Texture::from_file("tx.png") {
-        Err(e) => {
-            println!("Cannot open the texture: {}", e);
-            return;
-        }
-        Ok(im) => im,
+    // A generated map, so that the benchmark does not depend on any image on disk
+    let map = terrain::generate::heightmap(&GeneratorConfig {
+        seed: 42,
+        octaves: 7,
+        roughness: 0.5,
+        size: 1024,
+    });
+    let texture = terrain::generate::texture(&map);
+    // A quarter of the memory, lit and fogged through tables
+    let texture_palettized = terrain::generate::texture(&map).palettize();
+    let lightmap = Lightmap::new(
+        &map,
+        Sun {
+            azimuth: 135.,
+            elevation: 30.,
+            ambient: 0.3,
+            shading: true,
+            shadow_softness: None,
+        },
+    );
+
+    let terrain = Terrain {
+        map: &map,
+        texture: &texture,
+        lightmap: None,
+    };
+    let terrain_palettized = Terrain {
+        texture: &texture_palettized,
+        ..terrain
+    };
+    let terrain_lit = Terrain {
+        lightmap: Some(&lightmap),
+        ..terrain
     };
+    let terrain_lit_palettized = Terrain {
+        lightmap: Some(&lightmap),
+        ..terrain_palettized
+    };
+
+    let mut screen_fast = MemoryFramebuffer::new(320, 240, PixelFormat::BgrxBytes);
+    let mut screen_slow = MemoryFramebuffer::new(1920, 1080, PixelFormat::BgrxBytes);
 
-    let screen_fast = sdl::video::Surface::new(
-        &[SurfaceFlag::SWSurface],
-        320,
-        240,
-        32,
-        0xff0000,
-        0x00ff00,
-        0x0000ff,
-        0x000000ff,
-    )
-    .unwrap();
-
-    let screen_slow = sdl::video::Surface::new(
-        &[SurfaceFlag::SWSurface],
-        1920,
-        1080,
-        32,
-        0xff0000,
-        0x00ff00,
-        0x0000ff,
-        0x000000ff,
-    )
-    .unwrap();
-
-    let camera_slow = camera::Camera::new(
-        500.,
-        400.,
+    let camera_slow = Camera::new(
+        500.into(),
+        400.into(),
         200.into(),
-        2 * screen_slow.get_height() as i32 / 3,
+        2 * screen_slow.height() as i32 / 3,
     );
-    let camera_fast = camera::Camera::new(
-        500.,
-        400.,
+    let camera_fast = Camera::new(
+        500.into(),
+        400.into(),
         200.into(),
-        2 * screen_fast.get_height() as i32 / 3,
-    );
-
-    c.bench_function("draw_fast", |b| {
-        b.iter(|| {
-            renderer::draw(
-                black_box(&screen_fast),
-                &map,
-                &texture,
-                black_box(&camera_fast),
-                black_box(&bench_config_fast),
-            )
-        })
-    });
+        2 * screen_fast.height() as i32 / 3,
+    );
+    // Looking down at the ground, the terrain covers the whole screen long before distance_max
+    let camera_occluded = Camera::new(
+        500.into(),
+        400.into(),
+        200.into(),
+        3 * screen_slow.height() as i32,
+    );
 
-    c.bench_function("draw_slow", |b| {
-        b.iter(|| {
-            renderer::draw(
-                black_box(&screen_slow),
-                &map,
-                &texture,
-                black_box(&camera_slow),
-                black_box(&bench_config_slow),
-            )
-        })
-    });
+    bench(
+        c,
+        "draw_fast",
+        &mut screen_fast,
+        &terrain,
+        &camera_fast,
+        &bench_config_fast,
+    );
+    bench(
+        c,
+        "draw_slow",
+        &mut screen_slow,
+        &terrain,
+        &camera_slow,
+        &bench_config_slow,
+    );
+    bench(
+        c,
+        "draw_slow_lod",
+        &mut screen_slow,
+        &terrain,
+        &camera_slow,
+        &bench_config_slow_lod,
+    );
+    bench(
+        c,
+        "draw_slow_occluded",
+        &mut screen_slow,
+        &terrain,
+        &camera_occluded,
+        &bench_config_slow,
+    );
+    bench(
+        c,
+        "draw_slow_parallel",
+        &mut screen_slow,
+        &terrain,
+        &camera_slow,
+        &bench_config_slow_parallel,
+    );
+    bench(
+        c,
+        "draw_fast_integer",
+        &mut screen_fast,
+        &terrain,
+        &camera_fast,
+        &bench_config_fast_integer,
+    );
+    bench(
+        c,
+        "draw_slow_integer",
+        &mut screen_slow,
+        &terrain,
+        &camera_slow,
+        &bench_config_slow_integer,
+    );
+
+    // The native format of the handhelds, same frame as draw_fast
+    let mut screen_fast_rgb565 = MemoryFramebuffer::new(320, 240, PixelFormat::Rgb565);
+    bench(
+        c,
+        "draw_fast_rgb565",
+        &mut screen_fast_rgb565,
+        &terrain,
+        &camera_fast,
+        &bench_config_fast,
+    );
+    bench(
+        c,
+        "draw_fast_rgb565_dithered",
+        &mut screen_fast_rgb565,
+        &terrain,
+        &camera_fast,
+        &bench_config_fast_dithered,
+    );
+
+    bench(
+        c,
+        "draw_fast_palettized",
+        &mut screen_fast,
+        &terrain_palettized,
+        &camera_fast,
+        &bench_config_fast,
+    );
+    bench(
+        c,
+        "draw_slow_palettized",
+        &mut screen_slow,
+        &terrain_palettized,
+        &camera_slow,
+        &bench_config_slow,
+    );
+    bench(
+        c,
+        "draw_fast_lit",
+        &mut screen_fast,
+        &terrain_lit,
+        &camera_fast,
+        &bench_config_fast,
+    );
+    bench(
+        c,
+        "draw_fast_lit_palettized",
+        &mut screen_fast,
+        &terrain_lit_palettized,
+        &camera_fast,
+        &bench_config_fast,
+    );
 }
 
 criterion_group!(
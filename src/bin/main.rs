@@ -13,11 +13,13 @@ use sdl::{quit, InitFlag};
 use mountain::vector::Vector2;
 
 use mountain::camera::Camera;
-use mountain::config::{Config, ConfigError};
-use mountain::fixed_int::FixedInt10;
-use mountain::renderer::draw;
+use mountain::config::{Config, ConfigError, PlayerConfig};
+use mountain::fixed_int::{FixedInt10, FixedInt16};
+use mountain::framebuffer::{Framebuffer, SdlFramebuffer};
+use mountain::renderer::Renderer;
+use mountain::sky::Sky;
 use mountain::stats::Stats;
-use mountain::terrain::{HeightMap, Texture};
+use mountain::terrain::lighting::{Lightmap, Sun};
 use sdl::wm::{grab_input, GrabMode};
 
 mod others {
@@ -26,6 +28,13 @@ mod others {
     extern "C" {}
 }
 
+// Limits of the field of view in degrees, and the factor applied to it at each zoom step
+const MIN_FOV: f32 = 5.;
+const MAX_FOV: f32 = 120.;
+const ZOOM_STEP: f32 = 1.25;
+// Banking at each key press, 16 / 1024 of a turn is about 5.6 degrees
+const ROLL_STEP: FixedInt10 = FixedInt10::from_bits(16);
+
 #[derive(Default, Copy, Clone)]
 struct KeyPressedState {
     pub left_pressed: bool,
@@ -38,9 +47,32 @@ struct KeyPressedState {
     pub motion_initialized: bool,
 }
 
+/// The player settings converted once to fixed point, so that the frame update runs without
+/// floats
+struct Controls {
+    // Turns per pixel of mouse motion
+    turn_speed: FixedInt16,
+    // Pixels of horizon per pixel of mouse motion
+    pitch_speed: FixedInt10,
+}
+
+impl Controls {
+    fn new(config: &PlayerConfig) -> Controls {
+        // The horizontal sensitivity is in hundredths of radian per pixel, rounded to the nearest
+        // step of the heading
+        let turns = config.sensitivity_x / 100. / std::f32::consts::TAU;
+        let steps = (turns * (1 << FixedInt16::FRACTION_BITS) as f32).round();
+        Controls {
+            turn_speed: FixedInt16::from_bits(steps as i32),
+            pitch_speed: config.sensitivity_y,
+        }
+    }
+}
+
 fn process_events(
     camera: &mut Camera,
     config: &mut Config,
+    controls: &Controls,
     key_state: &mut KeyPressedState,
 ) -> bool {
     let mut displacement = Vector2 { x: 0, y: 0 };
@@ -96,11 +128,24 @@ fn process_events(
                         }
                         key_state.back_pressed = pressed
                     }
-                    Key::B => {
-                        if pressed {
-                            config.renderer.enable_filtering = !config.renderer.enable_filtering;
-                        }
+                    // Bank left and right
+                    Key::Q if pressed => camera.update_roll(-ROLL_STEP),
+                    Key::E if pressed => camera.update_roll(ROLL_STEP),
+                    Key::B if pressed => {
+                        config.renderer.enable_filtering = !config.renderer.enable_filtering;
                     }
+                    // Zoom in and out, like binoculars
+                    Key::Z if pressed => {
+                        config.renderer.fov = (config.renderer.fov / ZOOM_STEP).max(MIN_FOV)
+                    }
+                    Key::X if pressed => {
+                        config.renderer.fov = (config.renderer.fov * ZOOM_STEP).min(MAX_FOV)
+                    }
+                    // Move the sun around, the lightmap is rebuilt on the next frame
+                    Key::N if pressed => config.renderer.sun_azimuth -= 10.,
+                    Key::M if pressed => config.renderer.sun_azimuth += 10.,
+                    Key::J if pressed => config.renderer.sun_elevation -= 5.,
+                    Key::K if pressed => config.renderer.sun_elevation += 5.,
                     _ => (),
                 }
             }
@@ -108,8 +153,8 @@ fn process_events(
         }
     }
 
-    camera.update_angle(-(config.player.sensitivity_x * mouse_motion.x as f32) / 100.);
-    camera.horizon += (config.player.sensitivity_y * mouse_motion.y as f32) as i32;
+    camera.update_angle(-(controls.turn_speed * mouse_motion.x as i32));
+    camera.horizon += i32::from(controls.pitch_speed * mouse_motion.y as i32);
 
     if key_state.left_pressed || single_tap.left_pressed {
         displacement.x -= config.player.speed
@@ -124,10 +169,10 @@ fn process_events(
         displacement.y += config.player.speed
     }
 
-    camera.x +=
-        (displacement.x as f32) * camera.cos_angle + (displacement.y as f32) * camera.sin_angle;
-    camera.y +=
-        (displacement.x as f32) * camera.sin_angle + (displacement.y as f32) * camera.cos_angle;
+    let cos: FixedInt10 = camera.cos_angle.convert();
+    let sin: FixedInt10 = camera.sin_angle.convert();
+    camera.x += cos * displacement.x + sin * displacement.y;
+    camera.y += sin * displacement.x + cos * displacement.y;
 
     request_exit
 }
@@ -145,32 +190,41 @@ fn main() {
 
     println!("Loading textures");
 
-    let map = match HeightMap::from_file(config.map.heightmap.as_str()) {
+    let (map, texture) = match config.map.load_terrain() {
         Err(e) => {
-            println!("Cannot open the map: {}", e);
+            println!("{}", e);
             return;
         }
-        Ok(im) => im,
+        Ok(terrain) => terrain,
     };
 
-    let texture = match Texture::from_file(config.map.texture.as_str()) {
+    let mut renderer = match Sky::from_config(&config.sky) {
         Err(e) => {
-            println!("Cannot open the texture: {}", e);
+            println!("{}", e);
             return;
         }
-        Ok(im) => im,
+        Ok(sky) => Renderer::new(sky),
     };
 
+    let mut lightmap = Lightmap::from_config(&map, &config.renderer);
+
     sdl::init([InitFlag::Video].as_ref());
 
-    let screen = set_video_mode(
+    let surface = set_video_mode(
         config.screen.width as isize,
         config.screen.height as isize,
-        32,
+        config.screen.depth as isize,
         [SurfaceFlag::SWSurface].as_ref(),
         [VideoFlag::Fullscreen].as_ref(),
     )
     .unwrap();
+    let mut screen = match SdlFramebuffer::new(surface) {
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+        Ok(screen) => screen,
+    };
     set_cursor_visible(false);
     grab_input(GrabMode::On);
 
@@ -178,23 +232,40 @@ fn main() {
     let mut frame_ctr = Stats::default();
     let mut draw_ctr = Stats::default();
     let mut key_pressed = KeyPressedState::default();
+    let mut z_steps = 0;
 
-    let mut camera = Camera::new(500., 400., 200.into(), screen.get_height() as i32 / 2);
+    let controls = Controls::new(&config.player);
+    let mut camera = Camera::new(
+        500.into(),
+        400.into(),
+        200.into(),
+        screen.height() as i32 / 2,
+    );
 
     while !request_exit {
         frame_ctr.start_event();
 
-        if process_events(&mut camera, &mut config, &mut key_pressed) {
+        if process_events(&mut camera, &mut config, &controls, &mut key_pressed) {
             request_exit = true;
         }
-        camera.z = FixedInt10::from(config.player.height)
-            + map.get_interpolate(camera.x.into(), camera.y.into());
+        camera.z = FixedInt10::from(config.player.height) + map.get_interpolate(camera.x, camera.y);
+
+        if let Some(lightmap) = lightmap.as_mut() {
+            lightmap.update(&map, Sun::from_config(&config.renderer));
+        }
 
         draw_ctr.time(|| {
-            draw(&screen, &map, &texture, &camera, &config.renderer);
+            z_steps += renderer.draw(
+                &mut screen,
+                &map,
+                &texture,
+                lightmap.as_ref(),
+                &camera,
+                &config.renderer,
+            );
         });
 
-        screen.flip();
+        screen.surface().flip();
 
         let ms_elapsed = frame_ctr.end_event();
 
@@ -204,5 +275,9 @@ fn main() {
     }
     println!("Frame stats: {}", frame_ctr);
     println!("Draw stats: {}", draw_ctr);
+    println!(
+        "Steps in z per frame: {:.1}",
+        z_steps as f32 / draw_ctr.num_events() as f32
+    );
     quit();
 }
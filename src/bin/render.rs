@@ -0,0 +1,116 @@
+extern crate mountain;
+
+use std::env;
+use std::process::exit;
+use std::str::FromStr;
+
+use rgb::RGB8;
+
+use mountain::camera::Camera;
+use mountain::config::{Config, ConfigError};
+use mountain::fixed_int::FixedInt10;
+use mountain::framebuffer::{Framebuffer, MemoryFramebuffer, PixelFormat};
+use mountain::renderer::Renderer;
+use mountain::sky::Sky;
+use mountain::terrain::lighting::Lightmap;
+
+const USAGE: &str = "Usage: render <x> <y> <angle in turns> <horizon> <output.png>";
+
+fn parse_arg<T: FromStr>(args: &[String], index: usize, name: &str) -> Result<T, String> {
+    match args.get(index) {
+        None => Err(format!("Missing argument {}", name)),
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value for {}: {}", name, value)),
+    }
+}
+
+struct Options {
+    x: f32,
+    y: f32,
+    angle: f32,
+    horizon: i32,
+    output: String,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    Ok(Options {
+        x: parse_arg(args, 1, "x")?,
+        y: parse_arg(args, 2, "y")?,
+        angle: parse_arg(args, 3, "angle")?,
+        horizon: parse_arg(args, 4, "horizon")?,
+        output: parse_arg(args, 5, "output")?,
+    })
+}
+
+/// Report the error and exit with a failure status, so that scripts notice it
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    exit(1);
+}
+
+/// Render a single frame from the command line, without opening any window
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let options = match parse_args(&args) {
+        Ok(o) => o,
+        Err(e) => fail(format!("{}\n{}", e, USAGE)),
+    };
+
+    let config = match Config::from_config("mountain.toml") {
+        Ok(c) => c,
+        Err(ConfigError { message }) => fail(format!(
+            "Cannot read config file mountain.toml: {}",
+            message
+        )),
+    };
+
+    let (map, texture) = match config.map.load_terrain() {
+        Err(e) => fail(e),
+        Ok(terrain) => terrain,
+    };
+
+    let mut renderer = match Sky::from_config(&config.sky) {
+        Err(e) => fail(e),
+        Ok(sky) => Renderer::new(sky),
+    };
+
+    let mut screen = MemoryFramebuffer::new(
+        config.screen.width as usize,
+        config.screen.height as usize,
+        PixelFormat::RgbaBytes,
+    );
+
+    let mut camera = Camera::new(
+        options.x.into(),
+        options.y.into(),
+        0.into(),
+        options.horizon,
+    );
+    camera.update_angle(options.angle.into());
+    camera.z = FixedInt10::from(config.player.height) + map.get_interpolate(camera.x, camera.y);
+
+    let lightmap = Lightmap::from_config(&map, &config.renderer);
+
+    renderer.draw(
+        &mut screen,
+        &map,
+        &texture,
+        lightmap.as_ref(),
+        &camera,
+        &config.renderer,
+    );
+
+    // The renderer never writes the alpha channel, so we save the image without it
+    let pixels: Vec<RGB8> = screen
+        .pixels()
+        .chunks(4)
+        .map(|p| RGB8::new(p[0], p[1], p[2]))
+        .collect();
+
+    match lodepng::encode24_file(&options.output, &pixels, screen.width(), screen.height()) {
+        Err(e) => fail(format!("Cannot save the image {}: {}", options.output, e)),
+        Ok(()) => println!("Frame saved to {}", options.output),
+    }
+}
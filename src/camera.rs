@@ -1,32 +1,56 @@
-use crate::fixed_int::FixedInt10;
+use crate::fixed_int::{FixedInt10, FixedInt16};
 
+// Beyond this roll, in turns, the tilt of the horizon grows too fast to be usable. 160 / 1024 is
+// about 56 degrees.
+const MAX_ROLL: i32 = 160;
+
+/// Everything is in fixed point, so that moving the camera does not need any float. The angles
+/// are in turns, the heading with 16 bits so that slow mouse motions still turn the view.
 #[derive(Debug)]
 pub struct Camera {
-    pub x: f32,
-    pub y: f32,
+    pub x: FixedInt10,
+    pub y: FixedInt10,
     pub z: FixedInt10,
     pub horizon: i32,
-    angle: f32,
-    pub cos_angle: f32,
-    pub sin_angle: f32,
+    angle: FixedInt16,
+    pub cos_angle: FixedInt16,
+    pub sin_angle: FixedInt16,
+    // Banking, positive to the right
+    roll: FixedInt10,
+    pub tan_roll: FixedInt10,
 }
 
 impl Camera {
-    pub fn update_angle(&mut self, offset: f32) {
-        self.angle += offset;
+    pub fn update_angle(&mut self, offset: FixedInt16) {
+        // Keep the angle within a turn, the sine and cosine repeat anyway
+        self.angle = (self.angle + offset).fract();
         self.cos_angle = self.angle.cos();
         self.sin_angle = self.angle.sin();
     }
 
-    pub fn new(x: f32, y: f32, z: FixedInt10, horizon: i32) -> Camera {
+    /// Heading in turns, between 0 and 1
+    pub fn angle(&self) -> FixedInt16 {
+        self.angle
+    }
+
+    pub fn update_roll(&mut self, offset: FixedInt10) {
+        let roll = (self.roll + offset).to_bits().clamp(-MAX_ROLL, MAX_ROLL);
+        self.roll = FixedInt10::from_bits(roll);
+        // The cosine stays above 0.5 within the limits
+        self.tan_roll = self.roll.sin() / self.roll.cos();
+    }
+
+    pub fn new(x: FixedInt10, y: FixedInt10, z: FixedInt10, horizon: i32) -> Camera {
         Camera {
             x,
             y,
             z,
             horizon,
-            angle: 0.,
-            cos_angle: 1.,
-            sin_angle: 0.,
+            angle: 0.into(),
+            cos_angle: 1.into(),
+            sin_angle: 0.into(),
+            roll: 0.into(),
+            tan_roll: 0.into(),
         }
     }
 }
@@ -2,18 +2,116 @@ use std::fs;
 extern crate serde;
 extern crate serde_derive;
 
+use crate::fixed_int::FixedInt10;
+use crate::terrain::{generate, Border, HeightMap, Texture};
+use rgb::RGBA8;
 use serde_derive::Deserialize;
 
 #[derive(Deserialize)]
 pub struct ScreenConfig {
     pub width: i32,
     pub height: i32,
+    // Bits per pixel, 0 for the depth of the display
+    #[serde(default = "default_depth")]
+    pub depth: i32,
 }
 
+// The depth the window always had before it was configurable
+fn default_depth() -> i32 {
+    32
+}
+
+/// The map is either loaded from the texture and heightmap images, or generated
 #[derive(Deserialize)]
 pub struct MapConfig {
-    pub texture: String,
-    pub heightmap: String,
+    pub texture: Option<String>,
+    pub heightmap: Option<String>,
+    pub generator: Option<GeneratorConfig>,
+    #[serde(default)]
+    pub border: BorderConfig,
+    // Store the texture with 8 bits per texel, and light and fog it through tables. Such a
+    // texture is never filtered, enable_filtering only applies to RGBA textures.
+    #[serde(default)]
+    pub palettized: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    pub octaves: u32,
+    // Amplitude of each octave relative to the previous one, higher is more jagged
+    pub roughness: f32,
+    // Width and height of the map
+    pub size: usize,
+}
+
+impl MapConfig {
+    /// Load or generate the heightmap and the texture, with the configured border
+    pub fn load_terrain(&self) -> Result<(HeightMap, Texture), String> {
+        let (mut map, mut texture) = match (&self.generator, &self.heightmap, &self.texture) {
+            (Some(generator), _, _) => {
+                if generator.size == 0 {
+                    return Err("The generated map needs a size of at least 1".to_string());
+                }
+                let map = generate::heightmap(generator);
+                let texture = generate::texture(&map);
+                (map, texture)
+            }
+            (None, Some(heightmap), Some(texture)) => (
+                HeightMap::from_file(heightmap)
+                    .map_err(|e| format!("Cannot open the map: {}", e))?,
+                Texture::from_file(texture)
+                    .map_err(|e| format!("Cannot open the texture: {}", e))?,
+            ),
+            _ => {
+                return Err(
+                    "The map needs either a generator or both a heightmap and a texture"
+                        .to_string(),
+                )
+            }
+        };
+
+        map.set_border(self.border.height_border());
+        texture.set_border(self.border.texture_border());
+        if self.palettized {
+            texture = texture.palettize();
+        }
+        Ok((map, texture))
+    }
+}
+
+/// What lies outside of the map, written as border = { mode = "wrap" } in the config file
+#[derive(Deserialize, Copy, Clone, Default)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum BorderConfig {
+    // The map always repeated before the border was configurable
+    #[default]
+    Wrap,
+    Clamp,
+    Constant {
+        height: u8,
+        color: [u8; 3],
+    },
+}
+
+impl BorderConfig {
+    pub fn height_border(&self) -> Border<u8> {
+        match *self {
+            BorderConfig::Wrap => Border::Wrap,
+            BorderConfig::Clamp => Border::Clamp,
+            BorderConfig::Constant { height, .. } => Border::Constant(height),
+        }
+    }
+
+    pub fn texture_border(&self) -> Border<RGBA8> {
+        match *self {
+            BorderConfig::Wrap => Border::Wrap,
+            BorderConfig::Clamp => Border::Clamp,
+            BorderConfig::Constant { color, .. } => {
+                Border::Constant(RGBA8::new(color[0], color[1], color[2], 255))
+            }
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -21,16 +119,146 @@ pub struct PlayerConfig {
     pub height: i32,
     pub speed: i32,
     pub sensitivity_x: f32,
-    pub sensitivity_y: f32,
+    // Pixels of horizon per pixel of mouse motion
+    pub sensitivity_y: FixedInt10,
 }
 
+/// Every option missing from the config file takes its value from `Default`, so that the config
+/// files written before an option existed render as they did. The exception is scale_height,
+/// which changed meaning with the fov and renders the same only on 4:3 screens.
 #[derive(Deserialize)]
+#[serde(default)]
 pub struct RendererConfig {
     pub fog: bool,
     pub fog_start: i32,
     pub distance_max: i32,
+    // How the fog thickens from fog_start to distance_max
+    pub fog_model: FogModel,
+    // How fast the exponential models thicken past fog_start, the higher the sooner. The curve is
+    // scaled to reach only the sky at distance_max, so this is not a density per unit of distance.
+    pub fog_density: f32,
+    // Fog lying in the valleys, whatever the distance fog
+    pub height_fog: Option<HeightFogConfig>,
+    // Interpolate the texels close to the camera, not for palettized textures
     pub enable_filtering: bool,
+    // Horizontal field of view in degrees, the vertical one follows from the aspect ratio
+    pub fov: f32,
+    // Exaggerate or flatten the terrain. The heights are scaled by the focal length, half of the
+    // screen width at a fov of 90 degrees, times scale_height. Before the fov, they were scaled
+    // by the screen height: multiply the old value by 2 * height / width to keep the same look.
     pub scale_height: f32,
+    // Sample coarser levels of the heightmap and texture, and take bigger steps, at a distance
+    pub enable_lod: bool,
+    // Distance at which the first coarser level is used, each doubling of the distance then
+    // switches to the next level
+    pub lod_distance: i32,
+    // Shade the terrain from the slopes of the heightmap, with the sun at the given angles
+    pub enable_lighting: bool,
+    // Angles in degrees, the elevation is 0 on the horizon and 90 at the zenith
+    pub sun_azimuth: f32,
+    pub sun_elevation: f32,
+    // Light received by the faces that do not see the sun, between 0 and 1
+    pub ambient: f32,
+    // Let the terrain cast shadows, fading over shadow_softness units of height
+    pub enable_shadows: bool,
+    pub shadow_softness: f32,
+    // Number of threads rendering strips of columns in parallel, 1 renders everything on the
+    // calling thread. The threads are kept from one frame to the next: spawning them for each
+    // frame cost about 25µs per thread.
+    pub threads: usize,
+    // Render without any float or division in the loops, for the CPUs without an FPU
+    pub integer_only: bool,
+    // Hide the banding of the 16 bits screens
+    pub dithering: Dithering,
+}
+
+/// The renderer as it was before each of the options existed: a linear fog, a single thread and
+/// floats, without LOD, lighting or dithering. The rest are the values of the shipped
+/// mountain.toml, whose scale_height keeps the heights of its 1920x1080 screen.
+impl Default for RendererConfig {
+    fn default() -> RendererConfig {
+        RendererConfig {
+            fog: true,
+            fog_start: 1200,
+            distance_max: 1300,
+            fog_model: FogModel::Linear,
+            fog_density: 0.,
+            height_fog: None,
+            enable_filtering: true,
+            fov: 90.,
+            scale_height: 0.5625,
+            enable_lod: false,
+            lod_distance: 300,
+            enable_lighting: false,
+            sun_azimuth: 135.,
+            sun_elevation: 40.,
+            ambient: 0.4,
+            enable_shadows: false,
+            shadow_softness: 20.,
+            threads: 1,
+            integer_only: false,
+            dithering: Dithering::None,
+        }
+    }
+}
+
+/// Written as fog_model = "exponential_squared" in the config file
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FogModel {
+    Linear,
+    Exponential,
+    ExponentialSquared,
+}
+
+#[derive(Deserialize, Copy, Clone, PartialEq, Debug)]
+pub struct HeightFogConfig {
+    // Height of the top of the fog, which is the thickest at height 0, above 0
+    pub height: f32,
+    // Per unit of distance, the fog is exponential
+    pub density: f32,
+    pub color: [u8; 3],
+}
+
+/// Pattern of the ordered dithering, written as dithering = "bayer" in the config file
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Dithering {
+    None,
+    Bayer,
+    BlueNoise,
+}
+
+/// What is drawn above the terrain, and the color the far terrain fades into
+#[derive(Deserialize)]
+pub struct SkyConfig {
+    #[serde(flatten)]
+    pub mode: SkyMode,
+    // The color of the sky on the horizon when not set
+    pub fog_color: Option<[u8; 3]>,
+}
+
+// The color the sky had before it was configurable, when the [sky] section is missing
+impl Default for SkyConfig {
+    fn default() -> SkyConfig {
+        SkyConfig {
+            mode: SkyMode::Color {
+                color: [80, 120, 250],
+            },
+            fog_color: None,
+        }
+    }
+}
+
+/// Written as mode = "gradient" in the [sky] section
+#[derive(Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum SkyMode {
+    Color { color: [u8; 3] },
+    // 2 or 3 colors, from the horizon up
+    Gradient { colors: Vec<[u8; 3]> },
+    // A PNG covering a full turn, with its bottom row on the horizon
+    Panorama { image: String },
 }
 
 #[derive(Deserialize)]
@@ -39,6 +267,8 @@ pub struct Config {
     pub screen: ScreenConfig,
     pub map: MapConfig,
     pub player: PlayerConfig,
+    #[serde(default)]
+    pub sky: SkyConfig,
 }
 
 pub struct ConfigError {
@@ -64,6 +294,94 @@ impl From<toml::de::Error> for ConfigError {
 impl Config {
     pub fn from_config(file_path: &str) -> Result<Config, ConfigError> {
         let config_text = fs::read_to_string(file_path)?;
-        toml::from_str::<Config>(config_text.as_str()).map_err(|e| e.into())
+        Config::from_text(config_text.as_str())
+    }
+
+    fn from_text(config_text: &str) -> Result<Config, ConfigError> {
+        let config = toml::from_str::<Config>(config_text)?;
+        // The thickness of the height fog is divided by its height
+        if let Some(height_fog) = &config.renderer.height_fog {
+            if height_fog.height <= 0. {
+                return Err(ConfigError {
+                    message: "The height fog needs a height above 0".to_string(),
+                });
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::SkyMode;
+    use crate::config::{BorderConfig, Config, Dithering, FogModel, GeneratorConfig, MapConfig};
+
+    #[test]
+    fn defaults() {
+        // A config file written before any of the options existed
+        let config: Config = toml::from_str(
+            "[renderer]\nfog=true\nfog_start=1200\ndistance_max=1300\nenable_filtering=true\n\
+             scale_height=0.5\n\
+             [screen]\nwidth=1920\nheight=1080\n\
+             [map]\nheightmap=\"hm2.png\"\ntexture=\"tx2.png\"\n\
+             [player]\nheight=25\nspeed=2\nsensitivity_x=2\nsensitivity_y=8",
+        )
+        .unwrap();
+
+        let renderer = &config.renderer;
+        assert_eq!(renderer.fog_model, FogModel::Linear);
+        assert!(renderer.height_fog.is_none());
+        assert_eq!(renderer.fov, 90.);
+        assert!(!renderer.enable_lod && !renderer.enable_lighting && !renderer.integer_only);
+        assert_eq!(renderer.threads, 1);
+        assert_eq!(renderer.dithering, Dithering::None);
+        assert_eq!(config.screen.depth, 32);
+        assert!(matches!(config.map.border, BorderConfig::Wrap));
+        assert!(!config.map.palettized);
+        assert!(matches!(
+            config.sky.mode,
+            SkyMode::Color {
+                color: [80, 120, 250]
+            }
+        ));
+        assert!(config.sky.fog_color.is_none());
+    }
+
+    #[test]
+    fn generator_size() {
+        let map = |size| MapConfig {
+            texture: None,
+            heightmap: None,
+            generator: Some(GeneratorConfig {
+                seed: 1,
+                octaves: 2,
+                roughness: 0.5,
+                size,
+            }),
+            border: BorderConfig::Wrap,
+            palettized: false,
+        };
+        assert!(map(0).load_terrain().is_err());
+        let (heightmap, _) = map(1).load_terrain().unwrap();
+        assert_eq!(heightmap.width(), 1);
+    }
+
+    #[test]
+    fn height_fog_height() {
+        let config = |height| {
+            Config::from_text(&format!(
+                "[renderer]\nheight_fog={{ height={}, density=0.003, color=[200, 200, 210] }}\n\
+                 [screen]\nwidth=320\nheight=240\n\
+                 [map]\nheightmap=\"hm2.png\"\ntexture=\"tx2.png\"\n\
+                 [player]\nheight=25\nspeed=2\nsensitivity_x=2\nsensitivity_y=8",
+                height
+            ))
+        };
+        assert!(config(0).is_err());
+        assert!(config(-10).is_err());
+        assert_eq!(
+            config(60).ok().unwrap().renderer.height_fog.unwrap().height,
+            60.
+        );
     }
 }
@@ -1,104 +1,466 @@
-use std::convert::TryInto;
+use serde::de::{Error, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::max;
+use std::convert::TryFrom;
 use std::fmt;
-use std::fmt::{Debug, Formatter};
-use std::ops::{Add, BitAnd, Div, Mul, Shl, Shr, Sub};
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{
+    Add, AddAssign, BitAnd, Div, DivAssign, Mul, MulAssign, Neg, Shl, ShlAssign, Shr, ShrAssign,
+    Sub, SubAssign,
+};
+use std::str::FromStr;
 
+// Angles are in turns, the table has the precision of FixedInt10: a quarter of a turn is 256
+const QUARTER_TURN: i32 = 256;
+const TABLE_FRAC: u32 = 10;
+
+/// Sine of every angle of the first quarter of a turn, with 10 bits of fraction. The table is
+/// computed at compile time with a Taylor series in fixed point, so no float is ever involved.
+const SINES: [i32; QUARTER_TURN as usize + 1] = sine_table();
+
+const fn sine_table() -> [i32; QUARTER_TURN as usize + 1] {
+    // 2 pi with 40 bits of fraction
+    const TAU: i128 = 6_908_435_304_715;
+    const BITS: u32 = 40;
+
+    let mut table = [0; QUARTER_TURN as usize + 1];
+    let mut k = 0;
+    while k <= QUARTER_TURN as usize {
+        let x = TAU * k as i128 / (4 * QUARTER_TURN as i128);
+        let x2 = (x * x) >> BITS;
+        // sin x = x - x^3/3! + x^5/5! - ...
+        let mut term = x;
+        let mut sum = 0;
+        let mut n = 1;
+        while term != 0 {
+            sum += term;
+            term = -((term * x2) >> BITS) / ((n + 1) * (n + 2));
+            n += 2;
+        }
+        table[k] = ((sum + (1 << (BITS - 11))) >> (BITS - 10)) as i32;
+        k += 1;
+    }
+    table
+}
+
+/// Sine of an angle of the table, in 1024ths of a turn
+fn table_sine(angle: i32) -> i32 {
+    let angle = angle & (4 * QUARTER_TURN - 1);
+    let index = angle % QUARTER_TURN;
+    match angle / QUARTER_TURN {
+        0 => SINES[index as usize],
+        1 => SINES[(QUARTER_TURN - index) as usize],
+        2 => -SINES[index as usize],
+        _ => -SINES[(QUARTER_TURN - index) as usize],
+    }
+}
+
+/// A number in fixed point, stored in an i32 with FRAC bits of fraction. More bits give more
+/// precision but a smaller range. Going from one precision to another is always explicit, with
+/// `convert`.
 #[derive(Copy, Default, Eq, Ord, PartialOrd, PartialEq)]
-pub struct FixedInt10 {
+pub struct Fixed<const FRAC: u32> {
     value: i32,
 }
 
-impl FixedInt10 {
-    // To prepare for a potential macro / generic
+pub type FixedInt10 = Fixed<10>;
+/// For the angles that need to be finer than a 1024th of a turn
+pub type FixedInt16 = Fixed<16>;
+
+impl<const FRAC: u32> Fixed<FRAC> {
+    pub const FRACTION_BITS: i32 = FRAC as i32;
+
     fn exponent() -> i32 {
-        10
+        Self::FRACTION_BITS
     }
     fn multiplier() -> i32 {
-        2_i32.pow(FixedInt10::exponent().try_into().unwrap())
+        1 << FRAC
+    }
+
+    /// The raw value, the number multiplied by 2^FRACTION_BITS
+    pub fn to_bits(self) -> i32 {
+        self.value
+    }
+
+    pub const fn from_bits(value: i32) -> Self {
+        Fixed { value }
+    }
+
+    /// The same number with TO bits of fraction, the extra bits are lost when TO is smaller and
+    /// the integer part overflows when it is larger than what is left
+    pub fn convert<const TO: u32>(self) -> Fixed<TO> {
+        let value = if TO >= FRAC {
+            self.value << (TO - FRAC)
+        } else {
+            self.value >> (FRAC - TO)
+        };
+        Fixed { value }
+    }
+
+    /// Sine of an angle in turns, from a table of 1024 angles per turn with 10 bits of precision.
+    /// Angles with more bits of fraction are interpolated between the two nearest angles of the
+    /// table, so that they are not rounded to a 1024th of a turn.
+    pub fn sin(self) -> Self {
+        if FRAC <= TABLE_FRAC {
+            let value = table_sine(self.convert::<TABLE_FRAC>().value);
+            return Fixed::<TABLE_FRAC> { value }.convert();
+        }
+        let extra = FRAC - TABLE_FRAC;
+        let index = self.value >> extra;
+        let t = self.value & ((1 << extra) - 1);
+        let (from, to) = (table_sine(index), table_sine(index + 1));
+        Fixed {
+            value: (from << extra) + (to - from) * t,
+        }
+    }
+
+    /// Cosine of an angle in turns, see `sin`
+    pub fn cos(self) -> Self {
+        let quarter = Fixed::<TABLE_FRAC>::from_bits(QUARTER_TURN);
+        if FRAC <= TABLE_FRAC {
+            (self.convert::<TABLE_FRAC>() + quarter).sin().convert()
+        } else {
+            (self + quarter.convert()).sin()
+        }
+    }
+
+    /// Angle in turns of the point (x, y), between -1/2 and 1/2. The angle in the first eighth of
+    /// a turn is found by a binary search in the table of sines, there is no division.
+    pub fn atan2(y: Self, x: Self) -> Self {
+        let (ax, ay) = ((x.value as i64).abs(), (y.value as i64).abs());
+        let (small, large) = (ax.min(ay), ax.max(ay));
+        if large == 0 {
+            return Self::default();
+        }
+
+        // Largest angle up to an eighth of a turn with tan(angle) <= small / large
+        let (mut low, mut high) = (0, QUARTER_TURN / 2);
+        while low < high {
+            let middle = (low + high + 1) / 2;
+            let sin = SINES[middle as usize] as i64;
+            let cos = SINES[(QUARTER_TURN - middle) as usize] as i64;
+            if sin * large <= cos * small {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+
+        let mut value = if ay > ax { QUARTER_TURN - low } else { low };
+        if x.value < 0 {
+            value = 2 * QUARTER_TURN - value;
+        }
+        if y.value < 0 {
+            value = -value;
+        }
+        Fixed::<TABLE_FRAC> { value }.convert()
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.value.checked_add(rhs.value).map(Self::from_bits)
+    }
+
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self::from_bits(self.value.saturating_add(rhs.value))
+    }
+
+    pub fn wrapping_add(self, rhs: Self) -> Self {
+        Self::from_bits(self.value.wrapping_add(rhs.value))
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.value.checked_sub(rhs.value).map(Self::from_bits)
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self::from_bits(self.value.saturating_sub(rhs.value))
+    }
+
+    pub fn wrapping_sub(self, rhs: Self) -> Self {
+        Self::from_bits(self.value.wrapping_sub(rhs.value))
+    }
+
+    // The product goes through an i64, so that only a result out of range overflows
+    fn wide_mul(self, rhs: Self) -> i64 {
+        (self.value as i64 * rhs.value as i64) >> FRAC
+    }
+
+    pub fn checked_mul(self, rhs: Self) -> Option<Self> {
+        i32::try_from(self.wide_mul(rhs)).ok().map(Self::from_bits)
+    }
+
+    pub fn saturating_mul(self, rhs: Self) -> Self {
+        let product = self.wide_mul(rhs).clamp(i32::MIN as i64, i32::MAX as i64);
+        Self::from_bits(product as i32)
+    }
+
+    pub fn wrapping_mul(self, rhs: Self) -> Self {
+        Self::from_bits(self.wide_mul(rhs) as i32)
+    }
+
+    // The dividend is widened to an i64, so that it keeps its fraction
+    fn wide_div(self, rhs: Self) -> i64 {
+        ((self.value as i64) << FRAC) / rhs.value as i64
+    }
+
+    /// None when dividing by zero or when the result is out of range
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        if rhs.value == 0 {
+            None
+        } else {
+            i32::try_from(self.wide_div(rhs)).ok().map(Self::from_bits)
+        }
+    }
+
+    /// Panics when dividing by zero, like the integers
+    pub fn saturating_div(self, rhs: Self) -> Self {
+        let quotient = self.wide_div(rhs).clamp(i32::MIN as i64, i32::MAX as i64);
+        Self::from_bits(quotient as i32)
+    }
+
+    /// Panics when dividing by zero, like the integers
+    pub fn wrapping_div(self, rhs: Self) -> Self {
+        Self::from_bits(self.wide_div(rhs) as i32)
+    }
+
+    pub fn abs(self) -> Self {
+        if self.value < 0 {
+            -self
+        } else {
+            self
+        }
+    }
+
+    /// self when t is 0, other when t is 1
+    pub fn lerp(self, other: Self, t: Self) -> Self {
+        self + (other - self) * t
+    }
+
+    /// The nearest integer, halfway cases are rounded away from zero like f32::round
+    pub fn round(self) -> Self {
+        if FRAC == 0 {
+            return self;
+        }
+        let half = Self::from_bits(1 << (FRAC - 1));
+        let rounded = (self.abs() + half).floor();
+        if self.value < 0 {
+            -rounded
+        } else {
+            rounded
+        }
+    }
+
+    pub fn ceil(self) -> Self {
+        -(-self).floor()
+    }
+
+    /// Square root, rounded down. Negative numbers have none: debug builds panic, release builds
+    /// return 0.
+    #[track_caller]
+    pub fn sqrt(self) -> Self {
+        if self.value < 0 {
+            if cfg!(debug_assertions) {
+                panic!("Square root of a negative number: {:?}", self);
+            }
+            return Self::default();
+        }
+
+        // Bit by bit integer square root of value * 2^FRAC, which is the result * 2^FRAC
+        let mut remainder = (self.value as u64) << FRAC;
+        let mut root = 0_u64;
+        let mut bit = 1_u64 << 62;
+        while bit > remainder {
+            bit >>= 2;
+        }
+        while bit != 0 {
+            if remainder >= root + bit {
+                remainder -= root + bit;
+                root = (root >> 1) + bit;
+            } else {
+                root >>= 1;
+            }
+            bit >>= 2;
+        }
+        Self::from_bits(root as i32)
+    }
+
+    /// 1 / self
+    #[track_caller]
+    pub fn recip(self) -> Self {
+        Self::from(1) / self
+    }
+
+    /// The nearest fixed point number, or an error when out of range
+    fn from_f64(value: f64) -> Result<Self, String> {
+        let scaled = (value * Self::multiplier() as f64).round();
+        if scaled.is_nan() || scaled < i32::MIN as f64 || scaled > i32::MAX as f64 {
+            Err(format!(
+                "{} does not fit in a fixed point number with {} bits of fraction",
+                value, FRAC
+            ))
+        } else {
+            Ok(Self::from_bits(scaled as i32))
+        }
     }
 
     // zero out the non-integer part
-    pub fn floor(self) -> FixedInt10 {
-        FixedInt10 {
-            value: self.value & ((!0_i32) ^ (FixedInt10::multiplier() - 1)),
+    pub fn floor(self) -> Self {
+        Fixed {
+            value: self.value & ((!0_i32) ^ (Self::multiplier() - 1)),
         }
     }
 
-    // zero out the bits of the integer part
-    pub fn fract(self) -> FixedInt10 {
-        FixedInt10 {
-            value: self.value & (FixedInt10::multiplier() - 1),
+    // zero out the bits of the integer part, so self - self.floor() and never negative
+    pub fn fract(self) -> Self {
+        Fixed {
+            value: self.value & (Self::multiplier() - 1),
         }
     }
 }
 
-impl Debug for FixedInt10 {
+/// Debug builds check every operation, and report the operands of the one that overflows. Release
+/// builds wrap around, like the integers do.
+#[cold]
+#[track_caller]
+fn overflow(lhs: impl Debug, operation: &str, rhs: impl Debug) -> ! {
+    panic!("Fixed point overflow: {:?} {} {:?}", lhs, operation, rhs)
+}
+
+impl<const FRAC: u32> Debug for Fixed<FRAC> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "{:?} f10",
-            (self.value as f32) / (Self::multiplier() as f32)
+            "{:?} f{}",
+            (self.value as f32) / (Self::multiplier() as f32),
+            FRAC
         )
     }
 }
 
-impl Clone for FixedInt10 {
+/// Exact decimal value, every fixed point number has one that an f64 represents exactly
+impl<const FRAC: u32> Display for Fixed<FRAC> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let value = self.value as f64 / Self::multiplier() as f64;
+        Display::fmt(&value, f)
+    }
+}
+
+/// Parse a decimal number, rounded to the nearest fixed point number
+impl<const FRAC: u32> FromStr for Fixed<FRAC> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse::<f64>() {
+            Err(e) => Err(format!("Invalid number {}: {}", s, e)),
+            Ok(value) => Self::from_f64(value),
+        }
+    }
+}
+
+impl<const FRAC: u32> Serialize for Fixed<FRAC> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.value as f64 / Self::multiplier() as f64)
+    }
+}
+
+struct FixedVisitor<const FRAC: u32>;
+
+impl<'de, const FRAC: u32> Visitor<'de> for FixedVisitor<FRAC> {
+    type Value = Fixed<FRAC>;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "a number, or a string holding a number")
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<Self::Value, E> {
+        Fixed::from_f64(value as f64).map_err(E::custom)
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<Self::Value, E> {
+        Fixed::from_f64(value as f64).map_err(E::custom)
+    }
+
+    fn visit_f64<E: Error>(self, value: f64) -> Result<Self::Value, E> {
+        Fixed::from_f64(value).map_err(E::custom)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+        value.parse().map_err(E::custom)
+    }
+}
+
+/// Numbers in the config files can be written directly as fixed point
+impl<'de, const FRAC: u32> Deserialize<'de> for Fixed<FRAC> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FixedVisitor)
+    }
+}
+
+impl<const FRAC: u32> Clone for Fixed<FRAC> {
     fn clone(&self) -> Self {
         *self
     }
 }
 
-impl From<FixedInt10> for i32 {
-    fn from(val: FixedInt10) -> i32 {
-        val.value >> FixedInt10::exponent()
+// Rounds toward negative infinity, like `floor`
+impl<const FRAC: u32> From<Fixed<FRAC>> for i32 {
+    fn from(val: Fixed<FRAC>) -> i32 {
+        val.value >> FRAC
     }
 }
 
-impl From<FixedInt10> for usize {
-    fn from(val: FixedInt10) -> usize {
-        (val.value as u32 >> FixedInt10::exponent() as u32) as usize
+// Rounds toward negative infinity, negative values saturate to 0 like `f32 as usize`
+impl<const FRAC: u32> From<Fixed<FRAC>> for usize {
+    fn from(val: Fixed<FRAC>) -> usize {
+        max(val.value >> FRAC, 0) as usize
     }
 }
 
-impl From<FixedInt10> for u8 {
-    fn from(val: FixedInt10) -> u8 {
-        (val.value >> FixedInt10::exponent()) as u8
+// Rounds toward negative infinity and wraps outside of 0..256
+impl<const FRAC: u32> From<Fixed<FRAC>> for u8 {
+    fn from(val: Fixed<FRAC>) -> u8 {
+        (val.value >> FRAC) as u8
     }
 }
 
-impl From<FixedInt10> for f32 {
-    fn from(val: FixedInt10) -> f32 {
-        val.value as f32 / FixedInt10::multiplier() as f32
+impl<const FRAC: u32> From<Fixed<FRAC>> for f32 {
+    fn from(val: Fixed<FRAC>) -> f32 {
+        val.value as f32 / Fixed::<FRAC>::multiplier() as f32
     }
 }
 
-impl From<i32> for FixedInt10 {
+impl<const FRAC: u32> From<i32> for Fixed<FRAC> {
+    #[track_caller]
     fn from(other: i32) -> Self {
+        if cfg!(debug_assertions) && other.checked_mul(Self::multiplier()).is_none() {
+            overflow(other, "to fixed point with fraction bits", FRAC);
+        }
         Self {
-            value: other << FixedInt10::exponent(),
+            value: other << Self::exponent(),
         }
     }
 }
 
-impl From<u8> for FixedInt10 {
+impl<const FRAC: u32> From<u8> for Fixed<FRAC> {
     fn from(other: u8) -> Self {
         Self {
-            value: (other as i32) << FixedInt10::exponent(),
+            value: (other as i32) << Self::exponent(),
         }
     }
 }
 
-impl From<f32> for FixedInt10 {
+// Rounds toward zero
+impl<const FRAC: u32> From<f32> for Fixed<FRAC> {
     fn from(other: f32) -> Self {
-        let brought_to_power = other * FixedInt10::multiplier() as f32;
+        let brought_to_power = other * Self::multiplier() as f32;
         Self {
             value: brought_to_power as i32,
         }
     }
 }
 
-impl Shl<i32> for FixedInt10 {
-    type Output = FixedInt10;
+impl<const FRAC: u32> Shl<i32> for Fixed<FRAC> {
+    type Output = Self;
 
     fn shl(self, rhs: i32) -> Self::Output {
         Self {
@@ -107,8 +469,8 @@ impl Shl<i32> for FixedInt10 {
     }
 }
 
-impl Shr<i32> for FixedInt10 {
-    type Output = FixedInt10;
+impl<const FRAC: u32> Shr<i32> for Fixed<FRAC> {
+    type Output = Self;
 
     fn shr(self, rhs: i32) -> Self::Output {
         Self {
@@ -117,99 +479,227 @@ impl Shr<i32> for FixedInt10 {
     }
 }
 
-impl BitAnd<i32> for FixedInt10 {
-    type Output = FixedInt10;
+impl<const FRAC: u32> BitAnd<i32> for Fixed<FRAC> {
+    type Output = Self;
 
     fn bitand(self, rhs: i32) -> Self::Output {
         Self {
-            value: self.value & (rhs << FixedInt10::exponent()),
+            value: self.value & (rhs << Self::exponent()),
         }
     }
 }
 
-impl Div<i32> for FixedInt10 {
-    type Output = FixedInt10;
+impl<const FRAC: u32> Div<i32> for Fixed<FRAC> {
+    type Output = Self;
 
+    #[track_caller]
     fn div(self, rhs: i32) -> Self::Output {
-        Self {
-            value: self.value / rhs,
+        if cfg!(debug_assertions) {
+            match self.value.checked_div(rhs) {
+                Some(value) => Self { value },
+                None => overflow(self, "/", rhs),
+            }
+        } else {
+            Self {
+                value: self.value / rhs,
+            }
         }
     }
 }
 
-impl Add for FixedInt10 {
+impl<const FRAC: u32> Add for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn add(self, rhs: Self) -> Self::Output {
-        Self {
-            value: self.value + rhs.value,
+        if cfg!(debug_assertions) {
+            self.checked_add(rhs)
+                .unwrap_or_else(|| overflow(self, "+", rhs))
+        } else {
+            self.wrapping_add(rhs)
         }
     }
 }
 
-impl Add<i32> for FixedInt10 {
+impl<const FRAC: u32> Add<i32> for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn add(self, rhs: i32) -> Self::Output {
-        Self {
-            value: self.value + (rhs << FixedInt10::exponent()),
-        }
+        self + Self::from(rhs)
     }
 }
 
-impl Sub for FixedInt10 {
+impl<const FRAC: u32> Sub for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn sub(self, rhs: Self) -> Self::Output {
-        Self {
-            value: self.value - rhs.value,
+        if cfg!(debug_assertions) {
+            self.checked_sub(rhs)
+                .unwrap_or_else(|| overflow(self, "-", rhs))
+        } else {
+            self.wrapping_sub(rhs)
         }
     }
 }
 
-impl Sub<i32> for FixedInt10 {
+impl<const FRAC: u32> Sub<i32> for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn sub(self, rhs: i32) -> Self::Output {
-        Self {
-            value: self.value - (rhs << FixedInt10::exponent()),
-        }
+        self - Self::from(rhs)
     }
 }
 
-impl Mul<FixedInt10> for FixedInt10 {
+impl<const FRAC: u32> Mul<Fixed<FRAC>> for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn mul(self, rhs: Self) -> Self::Output {
-        Self {
-            value: (self.value * rhs.value) >> FixedInt10::exponent(),
+        if cfg!(debug_assertions) {
+            self.checked_mul(rhs)
+                .unwrap_or_else(|| overflow(self, "*", rhs))
+        } else {
+            self.wrapping_mul(rhs)
         }
     }
 }
 
-impl Mul<u8> for FixedInt10 {
+impl<const FRAC: u32> Mul<u8> for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn mul(self, rhs: u8) -> Self::Output {
-        Self {
-            value: (self.value * rhs as i32),
-        }
+        self * rhs as i32
     }
 }
 
-impl Mul<i32> for FixedInt10 {
+impl<const FRAC: u32> Mul<i32> for Fixed<FRAC> {
     type Output = Self;
 
+    #[track_caller]
     fn mul(self, rhs: i32) -> Self::Output {
-        Self {
-            value: self.value * rhs,
+        if cfg!(debug_assertions) {
+            match self.value.checked_mul(rhs) {
+                Some(value) => Self { value },
+                None => overflow(self, "*", rhs),
+            }
+        } else {
+            Self {
+                value: self.value.wrapping_mul(rhs),
+            }
+        }
+    }
+}
+
+impl<const FRAC: u32> Neg for Fixed<FRAC> {
+    type Output = Self;
+
+    #[track_caller]
+    fn neg(self) -> Self::Output {
+        if cfg!(debug_assertions) {
+            match self.value.checked_neg() {
+                Some(value) => Self { value },
+                None => overflow(0, "-", self),
+            }
+        } else {
+            Self {
+                value: self.value.wrapping_neg(),
+            }
         }
     }
 }
 
+impl<const FRAC: u32> Div<Fixed<FRAC>> for Fixed<FRAC> {
+    type Output = Self;
+
+    #[track_caller]
+    fn div(self, rhs: Self) -> Self::Output {
+        if cfg!(debug_assertions) {
+            self.checked_div(rhs)
+                .unwrap_or_else(|| overflow(self, "/", rhs))
+        } else {
+            self.wrapping_div(rhs)
+        }
+    }
+}
+
+impl<const FRAC: u32> AddAssign for Fixed<FRAC> {
+    #[track_caller]
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<const FRAC: u32> AddAssign<i32> for Fixed<FRAC> {
+    #[track_caller]
+    fn add_assign(&mut self, rhs: i32) {
+        *self = *self + rhs;
+    }
+}
+
+impl<const FRAC: u32> SubAssign for Fixed<FRAC> {
+    #[track_caller]
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<const FRAC: u32> SubAssign<i32> for Fixed<FRAC> {
+    #[track_caller]
+    fn sub_assign(&mut self, rhs: i32) {
+        *self = *self - rhs;
+    }
+}
+
+impl<const FRAC: u32> MulAssign for Fixed<FRAC> {
+    #[track_caller]
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+impl<const FRAC: u32> MulAssign<i32> for Fixed<FRAC> {
+    #[track_caller]
+    fn mul_assign(&mut self, rhs: i32) {
+        *self = *self * rhs;
+    }
+}
+
+impl<const FRAC: u32> DivAssign for Fixed<FRAC> {
+    #[track_caller]
+    fn div_assign(&mut self, rhs: Self) {
+        *self = *self / rhs;
+    }
+}
+
+impl<const FRAC: u32> DivAssign<i32> for Fixed<FRAC> {
+    #[track_caller]
+    fn div_assign(&mut self, rhs: i32) {
+        *self = *self / rhs;
+    }
+}
+
+impl<const FRAC: u32> ShlAssign<i32> for Fixed<FRAC> {
+    fn shl_assign(&mut self, rhs: i32) {
+        *self = *self << rhs;
+    }
+}
+
+impl<const FRAC: u32> ShrAssign<i32> for Fixed<FRAC> {
+    fn shr_assign(&mut self, rhs: i32) {
+        *self = *self >> rhs;
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::fixed_int::FixedInt10;
+    use crate::fixed_int::{Fixed, FixedInt10};
+    use proptest::prelude::*;
+    use serde_derive::Deserialize;
+    use std::convert::TryFrom;
 
     #[test]
     fn same_type() {
@@ -275,5 +765,395 @@ mod tests {
         assert_eq!(FixedInt10::from(5), origin << 1);
         assert_eq!(FixedInt10::from(1.25), origin >> 1);
         assert_eq!(FixedInt10::from(2), origin & 2);
+        assert_eq!(origin.to_bits(), 2560);
+        assert_eq!(FixedInt10::from_bits(-512), FixedInt10::from(-0.5));
+    }
+
+    #[test]
+    fn trigonometry() {
+        let tau = std::f32::consts::TAU;
+        // Every angle of a turn and a half, some of them negative
+        for bits in -1536..1536 {
+            let angle = FixedInt10::from_bits(bits);
+            let turns: f32 = angle.into();
+            let sin: f32 = angle.sin().into();
+            let cos: f32 = angle.cos().into();
+            assert!(
+                (sin - (turns * tau).sin()).abs() <= 0.001,
+                "sin({:?})",
+                angle
+            );
+            assert!(
+                (cos - (turns * tau).cos()).abs() <= 0.001,
+                "cos({:?})",
+                angle
+            );
+        }
+        assert_eq!(FixedInt10::from(0.25).sin(), 1.into());
+        assert_eq!(FixedInt10::from(0.5).cos(), (-1).into());
+        assert_eq!(FixedInt10::from(0).sin(), 0.into());
+    }
+
+    #[test]
+    fn atan2() {
+        // Going around the circle gives back the angle
+        for bits in -511..=512 {
+            let angle = FixedInt10::from_bits(bits);
+            let radius = 100;
+            let found = FixedInt10::atan2(angle.sin() * radius, angle.cos() * radius);
+            assert!(
+                (found.to_bits() - bits).abs() <= 1,
+                "atan2 of {:?} gives {:?}",
+                angle,
+                found
+            );
+        }
+        assert_eq!(FixedInt10::atan2(0.into(), 0.into()), 0.into());
+        assert_eq!(FixedInt10::atan2(1.into(), 0.into()), 0.25.into());
+        assert_eq!(FixedInt10::atan2(0.into(), (-5).into()), 0.5.into());
+        assert_eq!(FixedInt10::atan2((-3).into(), 3.into()), (-0.125).into());
+    }
+
+    #[test]
+    fn precisions() {
+        let origin = FixedInt10::from(2.75);
+        let precise: Fixed<16> = origin.convert();
+        assert_eq!(f32::from(precise), 2.75);
+        assert_eq!(precise.to_bits(), 180_224);
+        assert_eq!(precise.convert::<10>(), origin);
+
+        // Fewer bits lose the fraction, and keep the integer part
+        let coarse: Fixed<1> = origin.convert();
+        assert_eq!(f32::from(coarse), 2.5);
+        assert_eq!(i32::from(Fixed::<1>::from(1_000_000_000)), 1_000_000_000);
+
+        let negative = FixedInt10::from(-1.5).convert::<4>();
+        assert_eq!(f32::from(negative), -1.5);
+        assert_eq!(i32::from(negative), -2);
+
+        // Angles keep the precision of the table whatever the number of bits
+        let angle = Fixed::<16>::from(0.125);
+        let sin: f32 = angle.sin().into();
+        assert!((sin - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.001);
+        assert_eq!(format!("{:?}", angle), "0.125 f16");
+
+        // Between two angles of the table, the finer ones are interpolated instead of rounded
+        let step = Fixed::<16>::from_bits(1 << 6);
+        for bits in 0..64 {
+            let angle = Fixed::<16>::from(0.1) + Fixed::from_bits(bits);
+            let turns = f32::from(angle) * std::f32::consts::TAU;
+            assert!((f32::from(angle.sin()) - turns.sin()).abs() < 0.001);
+            assert!((f32::from(angle.cos()) - turns.cos()).abs() < 0.001);
+            assert!(angle.sin() <= (angle + step).sin());
+        }
+        assert_ne!(
+            Fixed::<16>::from_bits(6554).sin(),
+            Fixed::<16>::from_bits(6560).sin()
+        );
+    }
+
+    #[test]
+    fn wide_multiplication() {
+        // The product of the values does not fit in an i32, the result does
+        let a = FixedInt10::from(3000);
+        let b = FixedInt10::from(0.5);
+        assert_eq!(a * b, 1500.into());
+        assert_eq!(
+            FixedInt10::from(-1000) * FixedInt10::from(1000),
+            (-1_000_000).into()
+        );
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let big = FixedInt10::from(2_000_000);
+        let negative = FixedInt10::from(-2_000_000);
+        let max = FixedInt10::from_bits(i32::MAX);
+        let min = FixedInt10::from_bits(i32::MIN);
+
+        assert_eq!(big.checked_add(big), None);
+        assert_eq!(big.saturating_add(big), max);
+        assert_eq!(big.wrapping_add(big).to_bits(), -198_967_296);
+        assert_eq!(FixedInt10::from(1).checked_add(1.into()), Some(2.into()));
+
+        assert_eq!(negative.checked_sub(big), None);
+        assert_eq!(negative.saturating_sub(big), min);
+        assert_eq!(FixedInt10::from(3).checked_sub(1.into()), Some(2.into()));
+
+        assert_eq!(big.checked_mul(big), None);
+        assert_eq!(big.saturating_mul(big), max);
+        assert_eq!(negative.saturating_mul(big), min);
+        assert_eq!(big.checked_mul(0.5.into()), Some(1_000_000.into()));
+        assert_eq!(
+            FixedInt10::from(3).wrapping_mul(FixedInt10::from(0.25)),
+            0.75.into()
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Fixed point overflow: 2000000.0 f10 * 2000000.0 f10")]
+    fn overflow_in_debug() {
+        let big = FixedInt10::from(2_000_000);
+        let _ = big * big;
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Fixed point overflow: 3000000 to fixed point")]
+    fn conversion_overflow_in_debug() {
+        let _ = FixedInt10::from(3_000_000);
+    }
+
+    #[test]
+    fn assign_operators() {
+        let mut value = FixedInt10::from(2);
+        value += FixedInt10::from(0.5);
+        value -= 1;
+        value *= FixedInt10::from(2);
+        value /= FixedInt10::from(0.5);
+        assert_eq!(value, 6.into());
+        value += 2;
+        value *= 3;
+        value /= 4;
+        value -= FixedInt10::from(1);
+        assert_eq!(value, 5.into());
+        value <<= 1;
+        value >>= 2;
+        assert_eq!(value, 2.5.into());
+        assert_eq!(value.clamp(0.into(), 2.into()), 2.into());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(FixedInt10::from(2.5).round(), 3.into());
+        assert_eq!(FixedInt10::from(-2.5).round(), (-3).into());
+        assert_eq!(FixedInt10::from(-2.25).round(), (-2).into());
+        assert_eq!(FixedInt10::from(-2.25).ceil(), (-2).into());
+        assert_eq!(FixedInt10::from(2.25).ceil(), 3.into());
+        assert_eq!(FixedInt10::from(3).ceil(), 3.into());
+        assert_eq!(FixedInt10::from(2).sqrt(), FixedInt10::from_bits(1448));
+        assert_eq!(FixedInt10::from(4).recip(), 0.25.into());
+    }
+
+    #[test]
+    fn checked_division() {
+        let one = FixedInt10::from(1);
+        assert_eq!(one.checked_div(0.into()), None);
+        assert_eq!(FixedInt10::from(1_000_000).checked_div(0.25.into()), None);
+        assert_eq!(
+            FixedInt10::from(1_000_000).saturating_div(0.25.into()),
+            FixedInt10::from_bits(i32::MAX)
+        );
+        assert_eq!(one.checked_div(4.into()), Some(0.25.into()));
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(FixedInt10::from(2.5).to_string(), "2.5");
+        assert_eq!(FixedInt10::from(-3).to_string(), "-3");
+        assert_eq!(FixedInt10::from_bits(1).to_string(), "0.0009765625");
+        assert_eq!(format!("{:.2}", FixedInt10::from(0.75)), "0.75");
+
+        assert_eq!("0.1".parse::<FixedInt10>(), Ok(FixedInt10::from_bits(102)));
+        assert_eq!(" 7 ".parse::<FixedInt10>(), Ok(7.into()));
+        assert!("seven".parse::<FixedInt10>().is_err());
+        assert!("3000000".parse::<FixedInt10>().is_err());
+    }
+
+    #[test]
+    fn deserialize() {
+        #[derive(Deserialize)]
+        struct Values {
+            float: FixedInt10,
+            integer: FixedInt10,
+            text: Fixed<4>,
+        }
+
+        let values: Values = toml::from_str("float=2.5\ninteger=-3\ntext=\"0.0625\"").unwrap();
+        assert_eq!(values.float, 2.5.into());
+        assert_eq!(values.integer, (-3).into());
+        assert_eq!(values.text, Fixed::from_bits(1));
+
+        assert!(toml::from_str::<Values>("float=1e10\ninteger=0\ntext=\"0\"").is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Square root of a negative number")]
+    fn negative_sqrt_in_debug() {
+        let _ = FixedInt10::from(-1).sqrt();
+    }
+
+    fn any_fixed() -> impl Strategy<Value = FixedInt10> {
+        any::<i32>().prop_map(FixedInt10::from_bits)
+    }
+
+    // Every value is exact in a f32
+    fn exact_fixed() -> impl Strategy<Value = FixedInt10> {
+        (-(1 << 23)..(1 << 23)).prop_map(FixedInt10::from_bits)
+    }
+
+    // Below 1024, so that products and quotients by at least 1/1024 stay in range
+    fn small_fixed() -> impl Strategy<Value = FixedInt10> {
+        (-(1 << 20)..(1 << 20)).prop_map(FixedInt10::from_bits)
+    }
+
+    fn exact(value: FixedInt10) -> f64 {
+        value.to_bits() as f64 / 1024.
+    }
+
+    // One step of fixed point, plus the rounding of the f32 operands and result
+    fn is_close(actual: FixedInt10, expected: f32) -> bool {
+        (exact(actual) - expected as f64).abs()
+            <= 1. / 1024. + (expected.abs() * f32::EPSILON * 4.) as f64
+    }
+
+    // The checked, saturating and wrapping results against the exact one
+    fn check_widened(
+        exact: i64,
+        checked: Option<FixedInt10>,
+        saturating: FixedInt10,
+        wrapping: FixedInt10,
+    ) -> Result<(), TestCaseError> {
+        prop_assert_eq!(checked.map(FixedInt10::to_bits), i32::try_from(exact).ok());
+        prop_assert_eq!(
+            saturating.to_bits() as i64,
+            exact.clamp(i32::MIN as i64, i32::MAX as i64)
+        );
+        prop_assert_eq!(wrapping.to_bits(), exact as i32);
+        Ok(())
+    }
+
+    // Each property is asserted in one place: widened_arithmetic checks the exact bits of the
+    // checked, saturating and wrapping arithmetic, the others compare the operators with f32
+    proptest! {
+        #[test]
+        fn f32_round_trip(a in any_fixed()) {
+            let float = f32::from(a);
+            prop_assert!(
+                (float as f64 - exact(a)).abs() <= exact(a).abs() * f32::EPSILON as f64 / 2.,
+                "{:?} became {}", a, float
+            );
+            if a.to_bits().unsigned_abs() <= 1 << 24 {
+                prop_assert_eq!(FixedInt10::from(float), a, "through {}", float);
+            }
+        }
+
+        #[test]
+        fn from_f32_rounds_toward_zero(float in -2_000_000_f32..2_000_000.) {
+            let fixed = FixedInt10::from(float);
+            let error = float as f64 - exact(fixed);
+            prop_assert!(
+                error.abs() < 1. / 1024. && error * float as f64 >= 0.,
+                "{} became {:?}", float, fixed
+            );
+        }
+
+        #[test]
+        fn widened_arithmetic(a in any_fixed(), b in any_fixed()) {
+            let (x, y) = (a.to_bits() as i64, b.to_bits() as i64);
+            check_widened(x + y, a.checked_add(b), a.saturating_add(b), a.wrapping_add(b))?;
+            check_widened(x - y, a.checked_sub(b), a.saturating_sub(b), a.wrapping_sub(b))?;
+            check_widened((x * y) >> 10, a.checked_mul(b), a.saturating_mul(b), a.wrapping_mul(b))?;
+            if y == 0 {
+                prop_assert_eq!(a.checked_div(b), None);
+            } else {
+                check_widened((x << 10) / y, a.checked_div(b), a.saturating_div(b), a.wrapping_div(b))?;
+            }
+        }
+
+        #[test]
+        fn operators(a in small_fixed(), b in small_fixed(), n in -1000..1000) {
+            let (x, y) = (f32::from(a), f32::from(b));
+            prop_assert_eq!(f32::from(a + b), x + y);
+            prop_assert_eq!(f32::from(a - b), x - y);
+            prop_assert_eq!(f32::from(-a), -x);
+            prop_assert!(is_close(a * b, x * y), "{:?}", a * b);
+            prop_assert!(is_close(a * n, x * n as f32), "{:?}", a * n);
+            if b != FixedInt10::default() {
+                prop_assert!(is_close(a / b, x / y), "{:?}", a / b);
+            }
+            if n != 0 {
+                prop_assert!(is_close(a / n, x / n as f32), "{:?}", a / n);
+            }
+            prop_assert_eq!(a.cmp(&b), x.partial_cmp(&y).unwrap());
+            prop_assert_eq!(f32::from(a.min(b)), x.min(y));
+            prop_assert_eq!(f32::from(a.max(b)), x.max(y));
+        }
+
+        #[test]
+        fn floor_and_fract(a in any_fixed()) {
+            let (floor, fract) = (a.floor(), a.fract());
+            prop_assert_eq!(exact(floor), exact(a).floor());
+            prop_assert_eq!(fract, a.wrapping_sub(floor));
+            prop_assert!(
+                FixedInt10::default() <= fract && fract < 1.into(),
+                "{:?} has the fraction {:?}", a, fract
+            );
+        }
+
+        #[test]
+        fn rounding_against_f32(a in exact_fixed()) {
+            let float = f32::from(a);
+            prop_assert_eq!(f32::from(a.ceil()), float.ceil());
+            prop_assert_eq!(f32::from(a.round()), float.round());
+            prop_assert_eq!(f32::from(a.abs()), float.abs());
+        }
+
+        #[test]
+        fn square_root(a in (0..=i32::MAX).prop_map(FixedInt10::from_bits)) {
+            let root = a.sqrt().to_bits() as i64;
+            let square = (a.to_bits() as i64) << 10;
+            prop_assert!(
+                root * root <= square && square < (root + 1) * (root + 1),
+                "{:?} has the square root {:?}", a, a.sqrt()
+            );
+            prop_assert!(is_close(a.sqrt(), f32::from(a).sqrt()), "{:?}", a.sqrt());
+        }
+
+        #[test]
+        fn reciprocal(a in exact_fixed()) {
+            prop_assume!(a != FixedInt10::default());
+            prop_assert!(is_close(a.recip(), 1. / f32::from(a)), "{:?}", a.recip());
+        }
+
+        #[test]
+        fn interpolation(a in small_fixed(), b in small_fixed(), t in 0..=1024) {
+            let t = FixedInt10::from_bits(t);
+            let expected = f32::from(a) + (f32::from(b) - f32::from(a)) * f32::from(t);
+            prop_assert!(is_close(a.lerp(b, t), expected), "{:?}", a.lerp(b, t));
+        }
+
+        #[test]
+        fn integer_conversions(a in any_fixed(), n in -(1 << 21)..(1 << 21), byte in any::<u8>()) {
+            let floor = exact(a).floor();
+            prop_assert_eq!(i32::from(a), floor as i32);
+            prop_assert_eq!(usize::from(a), floor as usize);
+            prop_assert_eq!(u8::from(a), floor as i32 as u8);
+            prop_assert_eq!(FixedInt10::from(n).to_bits(), n << 10);
+            prop_assert_eq!(f32::from(FixedInt10::from(byte)), byte as f32);
+        }
+
+        #[test]
+        fn text_round_trip(a in any_fixed()) {
+            prop_assert_eq!(a.to_string().parse::<FixedInt10>(), Ok(a));
+        }
+
+        #[test]
+        fn sine_and_cosine(a in any_fixed()) {
+            let angle = f32::from(a.fract()) * std::f32::consts::TAU;
+            prop_assert!((f32::from(a.sin()) - angle.sin()).abs() <= 0.002, "{:?}", a.sin());
+            prop_assert!((f32::from(a.cos()) - angle.cos()).abs() <= 0.002, "{:?}", a.cos());
+        }
+
+        #[test]
+        fn arctangent(y in small_fixed(), x in small_fixed()) {
+            prop_assume!(x != FixedInt10::default() || y != FixedInt10::default());
+            let turns = f32::from(FixedInt10::atan2(y, x));
+            let expected = f32::from(y).atan2(f32::from(x)) / std::f32::consts::TAU;
+            let error = (turns - expected + 0.5).rem_euclid(1.) - 0.5;
+            prop_assert!(error.abs() <= 2. / 1024., "{} turns instead of {}", turns, expected);
+        }
     }
 }
@@ -0,0 +1,190 @@
+// from_masks maps the packed words of SDL to the order of the bytes they have on little-endian
+#[cfg(target_endian = "big")]
+compile_error!("Only little-endian targets are supported");
+
+/// Layout of a pixel in memory. The 16 bits formats are named after a native-endian packed word,
+/// from its most significant bit, like SDL's. The 32 bits formats are named after the order of
+/// their bytes in memory, hence the Bytes suffix: SDL's XRGB8888, a packed word, is `BgrxBytes`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Packed word, red in the 5 most significant bits
+    Rgb565,
+    /// Packed word, the most significant bit unused
+    Rgb555,
+    /// Bytes X, R, G, B in memory
+    XrgbBytes,
+    /// Bytes B, G, R, X in memory, SDL's XRGB8888
+    BgrxBytes,
+    /// Bytes R, G, B, A in memory, like the PNG images
+    RgbaBytes,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb565 | PixelFormat::Rgb555 => 2,
+            PixelFormat::XrgbBytes | PixelFormat::BgrxBytes | PixelFormat::RgbaBytes => 4,
+        }
+    }
+
+    /// The format with the given channel masks, which are packed words like the ones of SDL
+    pub fn from_masks(bytes_per_pixel: u8, r: u32, g: u32, b: u32) -> Result<PixelFormat, String> {
+        match (bytes_per_pixel, r, g, b) {
+            (2, 0xf800, 0x07e0, 0x001f) => Ok(PixelFormat::Rgb565),
+            (2, 0x7c00, 0x03e0, 0x001f) => Ok(PixelFormat::Rgb555),
+            (4, 0x0000_ff00, 0x00ff_0000, 0xff00_0000) => Ok(PixelFormat::XrgbBytes),
+            (4, 0x00ff_0000, 0x0000_ff00, 0x0000_00ff) => Ok(PixelFormat::BgrxBytes),
+            (4, 0x0000_00ff, 0x0000_ff00, 0x00ff_0000) => Ok(PixelFormat::RgbaBytes),
+            _ => Err(format!(
+                "Unsupported surface format: {} bytes per pixel, masks {:#x} {:#x} {:#x}",
+                bytes_per_pixel, r, g, b
+            )),
+        }
+    }
+}
+
+/// A surface the renderer can draw into
+pub trait Framebuffer {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    /// Number of bytes between the start of two consecutive lines
+    fn pitch(&self) -> usize;
+    fn pixel_format(&self) -> PixelFormat;
+    /// Give access to the raw pixels, the surface may need to be locked during that time
+    fn with_pixels<F: FnOnce(&mut [u8])>(&mut self, f: F);
+}
+
+/// A framebuffer stored in a plain Vec<u8>, without any padding between the lines
+pub struct MemoryFramebuffer {
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+    pixels: Vec<u8>,
+}
+
+impl MemoryFramebuffer {
+    pub fn new(width: usize, height: usize, format: PixelFormat) -> MemoryFramebuffer {
+        MemoryFramebuffer {
+            width,
+            height,
+            format,
+            pixels: vec![0; width * height * format.bytes_per_pixel()],
+        }
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl Framebuffer for MemoryFramebuffer {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pitch(&self) -> usize {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn with_pixels<F: FnOnce(&mut [u8])>(&mut self, f: F) {
+        f(&mut self.pixels)
+    }
+}
+
+/// An SDL surface, in one of the formats the renderer writes
+#[cfg(feature = "sdl")]
+pub struct SdlFramebuffer {
+    surface: sdl::video::Surface,
+    format: PixelFormat,
+}
+
+#[cfg(feature = "sdl")]
+impl SdlFramebuffer {
+    /// Fails when the surface has a format the renderer does not write, like 24 bits
+    pub fn new(surface: sdl::video::Surface) -> Result<SdlFramebuffer, String> {
+        let format = unsafe { &*(*surface.raw).format };
+        let format = PixelFormat::from_masks(
+            format.BytesPerPixel,
+            format.Rmask,
+            format.Gmask,
+            format.Bmask,
+        )?;
+        Ok(SdlFramebuffer { surface, format })
+    }
+
+    pub fn surface(&self) -> &sdl::video::Surface {
+        &self.surface
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl Framebuffer for SdlFramebuffer {
+    fn width(&self) -> usize {
+        self.surface.get_width() as usize
+    }
+
+    fn height(&self) -> usize {
+        self.surface.get_height() as usize
+    }
+
+    fn pitch(&self) -> usize {
+        unsafe { (*self.surface.raw).pitch as usize }
+    }
+
+    fn pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn with_pixels<F: FnOnce(&mut [u8])>(&mut self, f: F) {
+        // with_lock only accepts a Fn closure, so we smuggle the FnOnce through a Cell
+        let f = std::cell::Cell::new(Some(f));
+        self.surface.with_lock(|pixels| {
+            if let Some(f) = f.take() {
+                f(pixels);
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framebuffer::{Framebuffer, MemoryFramebuffer, PixelFormat};
+
+    #[test]
+    fn memory_layout() {
+        let mut fb = MemoryFramebuffer::new(3, 2, PixelFormat::RgbaBytes);
+        assert_eq!(fb.pitch(), 12);
+        assert_eq!(fb.pixels().len(), 24);
+
+        fb.with_pixels(|pixels| pixels[2 * 4 + 12] = 42);
+        assert_eq!(fb.pixels()[20], 42);
+
+        let fb = MemoryFramebuffer::new(3, 2, PixelFormat::Rgb565);
+        assert_eq!(fb.pitch(), 6);
+        assert_eq!(fb.pixels().len(), 12);
+    }
+
+    #[test]
+    fn masks() {
+        // SDL's XRGB8888 is a packed word, its bytes are B, G, R, X in memory
+        assert_eq!(
+            PixelFormat::from_masks(4, 0x00ff_0000, 0x0000_ff00, 0x0000_00ff),
+            Ok(PixelFormat::BgrxBytes)
+        );
+        assert_eq!(
+            PixelFormat::from_masks(2, 0xf800, 0x07e0, 0x001f),
+            Ok(PixelFormat::Rgb565)
+        );
+        // 24 bits surfaces are not written by the renderer
+        assert!(PixelFormat::from_masks(3, 0xff_0000, 0x00_ff00, 0x00_00ff).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 pub mod camera;
 pub mod config;
 pub mod fixed_int;
+pub mod framebuffer;
 pub mod renderer;
+pub mod sky;
 pub mod stats;
 pub mod terrain;
 pub mod vector;
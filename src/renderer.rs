@@ -1,125 +1,504 @@
 use crate::camera::Camera;
-use crate::config::RendererConfig;
+use crate::config::{Dithering, RendererConfig};
 use crate::fixed_int::FixedInt10;
+use crate::framebuffer::{Framebuffer, PixelFormat};
+use crate::sky::{gradient_color, Background, Sky};
 use crate::terrain;
+use crate::terrain::lighting::{shade, Lightmap};
 use crate::vector::Vector2;
 use rgb::RGBA8;
-use sdl::video::{Color, Surface};
 use std::cmp::{max, min};
+use std::ops::Range;
 
-fn get_pitch(surface: &Surface) -> u16 {
-    unsafe { (*surface.raw).pitch }
+mod colormap;
+mod dither;
+mod fog;
+mod integer;
+mod pixel;
+mod tables;
+mod workers;
+
+use colormap::Colormap;
+use dither::{Bayer, BlueNoise, Dithered};
+use pixel::{BgrxBytes, Pixel, Rgb555, Rgb565, RgbaBytes, XrgbBytes};
+use tables::{Key, Tables};
+use workers::{Task, Workers};
+
+/// The part of the screen a strip of columns draws into
+struct Strip<'s> {
+    // A slice of each row of the screen, from the top
+    rows: Vec<&'s mut [u8]>,
+    // Column of the screen at the left of the strip, so that the dithering of the strips lines up
+    first_column: usize,
 }
 
-fn set_color(image: &mut [u8], i: usize, j: usize, pitch: usize, value: RGBA8) {
-    let pixel_offset = i * 4 + j * pitch;
-    image[pixel_offset] = value.b;
-    image[pixel_offset + 1] = value.g;
-    image[pixel_offset + 2] = value.r;
+/// Draw the pixels of column i from jmin to jmax, counted from the bottom of the strip
+fn draw_line<P: Pixel>(strip: &mut Strip, i: usize, jmin: usize, jmax: usize, value: RGBA8) {
+    let column = i + strip.first_column;
+    let encoded = P::encode(value, 0);
+    let offset = i * P::BYTES;
+    let height = strip.rows.len();
+    // Go up from jmin, one row at a time
+    for (row, j) in strip.rows[height - jmax..height - jmin]
+        .iter_mut()
+        .rev()
+        .zip(jmin..)
+    {
+        let encoded = if P::DITHERED {
+            P::encode(value, P::threshold(column, j))
+        } else {
+            encoded
+        };
+        P::write(&mut row[offset..offset + P::BYTES], encoded);
+    }
 }
 
-fn draw_line(
-    image: &mut [u8],
+/// Draw the pixels of column i from jmin to jmax like draw_line, with a color for each row
+fn draw_pixels<P: Pixel>(
+    strip: &mut Strip,
     i: usize,
     jmin: usize,
     jmax: usize,
-    image_h: usize,
-    pitch: usize,
-    value: RGBA8,
+    color: impl Fn(usize) -> RGBA8,
 ) {
-    for height in jmin..jmax {
-        set_color(image, i, image_h - height - 1, pitch, value);
+    let column = i + strip.first_column;
+    let offset = i * P::BYTES;
+    let height = strip.rows.len();
+    for (row, j) in strip.rows[height - jmax..height - jmin]
+        .iter_mut()
+        .rev()
+        .zip(jmin..)
+    {
+        let encoded = P::encode(color(j), P::threshold(column, j));
+        P::write(&mut row[offset..offset + P::BYTES], encoded);
     }
 }
 
-pub fn draw(
-    screen: &Surface,
-    map: &terrain::HeightMap,
-    texture: &terrain::Texture,
-    camera: &Camera,
-    config: &RendererConfig,
-) {
-    let screen_w = screen.get_width() as i32;
-    let screen_h = screen.get_height() as i32;
+/// Blend the color with the sky, by the weight of the sky
+fn blend_fog(color: RGBA8, sky: RGBA8, sky_weight: FixedInt10) -> RGBA8 {
+    let texture_weight = FixedInt10::from(1) - sky_weight;
+    RGBA8 {
+        r: (texture_weight * color.r + sky_weight * sky.r).into(),
+        g: (texture_weight * color.g + sky_weight * sky.g).into(),
+        b: (texture_weight * color.b + sky_weight * sky.b).into(),
+        a: 0,
+    }
+}
+
+/// Level of the mip chain to use at distance z, out of levels
+fn lod_level(z: i32, lod_distance: i32, levels: usize) -> usize {
+    let mut level = 0;
+    let mut distance = lod_distance;
+    while z >= distance && level + 1 < levels {
+        level += 1;
+        distance *= 2;
+    }
+    level
+}
 
-    let pitch = get_pitch(screen) as usize;
-    let sky = RGBA8::new(80, 120, 250, 0);
+/// Everything needed to render the columns of a frame, shared by all the strips
+struct Scene<'a> {
+    map: &'a terrain::HeightMap,
+    texture: &'a terrain::Texture,
+    lightmap: Option<&'a Lightmap>,
+    camera: &'a Camera,
+    config: &'a RendererConfig,
+    screen_w: i32,
+    screen_h: i32,
+    sky: &'a Sky,
+    // Color the far terrain fades into
+    fog_color: RGBA8,
+    // Color of the gradient for each row above the horizon, up to a screen height
+    sky_rows: Vec<RGBA8>,
+    // Columns of the panorama per pixel, and the one in the middle of the screen
+    panorama_step: FixedInt10,
+    panorama_center: FixedInt10,
+    // Tables for the lighting and fog of a palettized texture
+    colormap: Option<Colormap<'a>>,
+    // Height of the horizon for each column of the screen
+    horizons: Vec<FixedInt10>,
+    tables: &'a Tables,
+    levels: usize,
+}
 
-    screen.fill(Color::RGB(sky.r, sky.g, sky.b));
+impl<'a> Scene<'a> {
+    /// Render the given columns of the screen into the strip, where the first of them is at the
+    /// left of the strip. Return the number of steps taken in z.
+    fn draw_strip<P: Pixel>(&self, strip: &mut Strip, columns: Range<i32>) -> usize {
+        if self.config.integer_only {
+            self.draw_strip_integer::<P>(strip, columns)
+        } else {
+            self.draw_strip_float::<P>(strip, columns)
+        }
+    }
 
-    let horizon = FixedInt10::from(camera.horizon);
-    let scale_height = ((screen_h as f32) * config.scale_height) as i32;
+    fn draw_strip_float<P: Pixel>(&self, strip: &mut Strip, columns: Range<i32>) -> usize {
+        let (camera, config) = (self.camera, self.config);
+        let cos = f32::from(camera.cos_angle);
+        let sin = f32::from(camera.sin_angle);
+        let screen_h = self.screen_h;
 
-    screen.with_lock(|screen_pixels| {
-        let mut max_height = vec![0; screen_w as usize];
+        let mut max_height = vec![0; columns.len()];
+        // Columns that still have pixels to fill, there is nothing left to draw when none are
+        let mut open_columns = columns.len();
+        let mut z_steps = 0;
 
-        for z in 1..config.distance_max {
+        let mut z = 1;
+        while z < config.distance_max && open_columns > 0 {
+            let level = if config.enable_lod {
+                lod_level(z, config.lod_distance, self.levels)
+            } else {
+                0
+            };
+            let fog = self.fog_weight(z);
             let zf = z as f32;
+            let half_width = zf * self.tables.tan_half_fov;
             let left = Vector2 {
-                x: FixedInt10::from((-camera.cos_angle * zf - camera.sin_angle * zf) + camera.x),
-                y: FixedInt10::from((camera.sin_angle * zf - camera.cos_angle * zf) + camera.y),
+                x: FixedInt10::from(-sin * zf - cos * half_width) + camera.x,
+                y: FixedInt10::from(-cos * zf + sin * half_width) + camera.y,
             };
 
             let right = Vector2 {
-                x: FixedInt10::from((camera.cos_angle * zf - camera.sin_angle * zf) + camera.x),
-                y: FixedInt10::from((-camera.sin_angle * zf - camera.cos_angle * zf) + camera.y),
+                x: FixedInt10::from(-sin * zf + cos * half_width) + camera.x,
+                y: FixedInt10::from(-cos * zf - sin * half_width) + camera.y,
             };
 
             let stride = Vector2 {
-                x: (right.x - left.x) / screen_w,
-                y: (right.y - left.y) / screen_w,
+                x: (right.x - left.x) / self.screen_w,
+                y: (right.y - left.y) / self.screen_w,
             };
 
-            for i in 0..screen_w {
-                let height_on_hm = if config.enable_filtering && z < 100 {
-                    map.get_interpolate(left.x + stride.x * i, left.y + stride.y * i)
-                } else {
-                    map.get(left.x + stride.x * i, left.y + stride.y * i)
-                };
-                let real_height: FixedInt10 = (height_on_hm - camera.z)
+            for i in columns.clone() {
+                let column = (i - columns.start) as usize;
+                if max_height[column] >= screen_h {
+                    continue;
+                }
+                let x = left.x + stride.x * i;
+                let y = left.y + stride.y * i;
+                let height = self.height(x, y, z, level);
+                let real_height: FixedInt10 = (height - camera.z)
                     // trick here: scale_height AND z should be brought to fixed float, however
                     // the (<< PRECISION) cancel each other
-                    * scale_height
+                    * self.tables.scale_height
                     / z
-                    + horizon;
+                    + self.horizons[i as usize];
 
                 let real_height: i32 = max(0, real_height.into());
 
-                if real_height > max_height[i as usize] {
-                    let texture_value = if config.enable_filtering && z < 100 {
-                        texture.get_interpolate(left.x + stride.x * i, left.y + stride.y * i)
-                    } else {
-                        texture.get(left.x + stride.x * i, left.y + stride.y * i)
-                    };
-
-                    let texture_value = if config.fog && z > config.fog_start {
-                        let sky_weight = FixedInt10::from(z - config.fog_start)
-                            / (config.distance_max - config.fog_start);
-                        let texture_weight = FixedInt10::from(1) - sky_weight;
-
-                        RGBA8 {
-                            r: (texture_weight * texture_value.r + sky_weight * sky.r).into(),
-                            g: (texture_weight * texture_value.g + sky_weight * sky.g).into(),
-                            b: (texture_weight * texture_value.b + sky_weight * sky.b).into(),
-                            a: 0,
-                        }
-                    } else {
-                        texture_value
-                    };
-
-                    draw_line(
-                        screen_pixels,
-                        i as usize,
-                        max_height[i as usize] as usize,
+                if real_height > max_height[column] {
+                    draw_line::<P>(
+                        strip,
+                        column,
+                        max_height[column] as usize,
                         min(real_height, screen_h) as usize,
-                        screen_h as usize,
-                        pitch,
-                        texture_value,
+                        self.color(x, y, z, height, level, fog),
                     );
-                    max_height[i as usize] = real_height
+                    max_height[column] = real_height;
+                    if real_height >= screen_h {
+                        open_columns -= 1;
+                    }
+                }
+            }
+
+            // Each level is twice as coarse as the previous one, so are the steps
+            z += 1 << level;
+            z_steps += 1;
+        }
+
+        self.draw_sky::<P>(strip, &max_height);
+        z_steps
+    }
+
+    /// Height of the terrain at (x, y), seen from a distance z
+    fn height(&self, x: FixedInt10, y: FixedInt10, z: i32, level: usize) -> FixedInt10 {
+        if self.config.enable_filtering && z < 100 {
+            self.map.get_interpolate(x, y)
+        } else {
+            self.map.get_lod(x, y, level)
+        }
+    }
+
+    /// Weight of the sky in the color of the terrain at a distance z, if there is some fog there
+    fn fog_weight(&self, z: i32) -> Option<FixedInt10> {
+        match self.tables.fog_weights.get(z as usize) {
+            Some(&weight) if weight > 0.into() => Some(weight),
+            _ => None,
+        }
+    }
+
+    /// Color of the terrain at (x, y) and at the given height, seen from a distance z, lit and
+    /// fogged
+    fn color(
+        &self,
+        x: FixedInt10,
+        y: FixedInt10,
+        z: i32,
+        height: FixedInt10,
+        level: usize,
+        fog: Option<FixedInt10>,
+    ) -> RGBA8 {
+        if let Some(height_fog) = &self.tables.height_fog {
+            let weight = height_fog.weight(z, height);
+            if weight > 0.into() {
+                let color = self.color_in_fog(x, y, z, level, None);
+                let color = blend_fog(color, height_fog.color, weight);
+                return match fog {
+                    Some(sky_weight) => blend_fog(color, self.fog_color, sky_weight),
+                    None => color,
+                };
+            }
+        }
+        self.color_in_fog(x, y, z, level, fog)
+    }
+
+    /// Color of the terrain at (x, y) seen from a distance z, lit and blended with the sky by
+    /// the weight of the fog
+    fn color_in_fog(
+        &self,
+        x: FixedInt10,
+        y: FixedInt10,
+        z: i32,
+        level: usize,
+        fog: Option<FixedInt10>,
+    ) -> RGBA8 {
+        if let Some(colormap) = &self.colormap {
+            return colormap.color(x, y, level, self.lightmap, fog);
+        }
+
+        let texture_value = if self.config.enable_filtering && z < 100 {
+            self.texture.get_interpolate(x, y)
+        } else {
+            self.texture.get_lod(x, y, level)
+        };
+
+        let texture_value = match self.lightmap {
+            Some(lightmap) => shade(texture_value, lightmap.get_lod(x, y, level)),
+            None => texture_value,
+        };
+
+        match fog {
+            Some(sky_weight) => blend_fog(texture_value, self.fog_color, sky_weight),
+            None => texture_value,
+        }
+    }
+
+    /// The sky covers whatever is left above the terrain
+    fn draw_sky<P: Pixel>(&self, strip: &mut Strip, max_height: &[i32]) {
+        let screen_h = self.screen_h;
+        for (column, &height) in max_height.iter().enumerate() {
+            let jmin = min(height, screen_h) as usize;
+            let jmax = screen_h as usize;
+            let screen_column = column + strip.first_column;
+            let horizon = i32::from(self.horizons[screen_column]);
+            match self.sky.background() {
+                Background::Color(color) => draw_line::<P>(strip, column, jmin, jmax, *color),
+                // Below the horizon, the gradient keeps its first color
+                Background::Gradient(_) => draw_pixels::<P>(strip, column, jmin, jmax, |j| {
+                    self.sky_rows[(j as i32 - horizon).clamp(0, screen_h) as usize]
+                }),
+                // Same scale in both directions, the bottom row of the panorama on the horizon
+                Background::Panorama(panorama) => {
+                    let u = self.panorama_center
+                        + self.panorama_step * (screen_column as i32 - self.screen_w / 2);
+                    let bottom = panorama.height as i32 - 1;
+                    draw_pixels::<P>(strip, column, jmin, jmax, |j| {
+                        let v = bottom - i32::from(self.panorama_step * (j as i32 - horizon));
+                        panorama.at(u.into(), v)
+                    })
                 }
             }
         }
-        true
-    });
+    }
+}
+
+/// Renders the frames under a sky, and keeps what it derives from the configuration from one
+/// frame to the next, as well as the threads rendering the strips
+pub struct Renderer {
+    sky: Sky,
+    tables: Option<Tables>,
+    workers: Workers,
+}
+
+impl Renderer {
+    pub fn new(sky: Sky) -> Renderer {
+        Renderer {
+            sky,
+            tables: None,
+            workers: Workers::default(),
+        }
+    }
+
+    /// Render a frame and return the number of steps taken in z, added over all the strips when
+    /// rendering on several threads
+    pub fn draw<F: Framebuffer>(
+        &mut self,
+        screen: &mut F,
+        map: &terrain::HeightMap,
+        texture: &terrain::Texture,
+        lightmap: Option<&Lightmap>,
+        camera: &Camera,
+        config: &RendererConfig,
+    ) -> usize {
+        let screen_w = screen.width() as i32;
+        let screen_h = screen.height() as i32;
+
+        let format = screen.pixel_format();
+        let pitch = screen.pitch();
+
+        // With the camera banking, the horizon is tilted: each column has its own height for it
+        let horizons: Vec<FixedInt10> = (0..screen_w)
+            .map(|i| FixedInt10::from(camera.horizon) + camera.tan_roll * (i - screen_w / 2))
+            .collect();
+        let sky = &self.sky;
+        let fog_color = sky.fog_color();
+        let palette = texture.palettized().map_or(&[][..], |t| t.palette());
+        let tables = Tables::update(
+            &mut self.tables,
+            Key::new(config, screen_w, fog_color),
+            palette,
+        );
+
+        let levels = min(map.levels(), texture.levels());
+        let sky_rows = match sky.background() {
+            Background::Gradient(colors) => (0..=screen_h)
+                .map(|row| gradient_color(colors, FixedInt10::from(row) / screen_h))
+                .collect(),
+            _ => Vec::new(),
+        };
+        // The panorama covers a full turn, the screen shows fov degrees of it
+        let panorama_width = match sky.background() {
+            Background::Panorama(panorama) => panorama.width as f32,
+            _ => 0.,
+        };
+        let panorama_step = FixedInt10::from(panorama_width * config.fov / 360. / screen_w as f32);
+        // Turning left moves the panorama to the right
+        let panorama_center = (-camera.angle() * panorama_width as i32).convert();
+        let scene = Scene {
+            map,
+            texture,
+            lightmap,
+            camera,
+            config,
+            screen_w,
+            screen_h,
+            sky,
+            fog_color,
+            sky_rows,
+            panorama_step,
+            panorama_center,
+            colormap: texture
+                .palettized()
+                .map(|t| Colormap::new(t, &tables.fog_colors)),
+            horizons,
+            tables,
+            levels: lightmap.map_or(levels, |l| min(levels, l.levels())),
+        };
+
+        let threads = config.threads.clamp(1, screen_w as usize);
+        let workers = &mut self.workers;
+        let mut z_steps = 0;
+        screen.with_pixels(|screen_pixels| {
+            // The format is only looked at here, everything below is specialized for it
+            z_steps = match format {
+                PixelFormat::Rgb565 => {
+                    draw_packed::<Rgb565>(&scene, screen_pixels, pitch, threads, workers)
+                }
+                PixelFormat::Rgb555 => {
+                    draw_packed::<Rgb555>(&scene, screen_pixels, pitch, threads, workers)
+                }
+                PixelFormat::XrgbBytes => {
+                    draw_frame::<XrgbBytes>(&scene, screen_pixels, pitch, threads, workers)
+                }
+                PixelFormat::BgrxBytes => {
+                    draw_frame::<BgrxBytes>(&scene, screen_pixels, pitch, threads, workers)
+                }
+                PixelFormat::RgbaBytes => {
+                    draw_frame::<RgbaBytes>(&scene, screen_pixels, pitch, threads, workers)
+                }
+            };
+        });
+        z_steps
+    }
+}
+
+/// Only the 16 bits formats are dithered, the others keep every bit of the colors
+fn draw_packed<P: Pixel>(
+    scene: &Scene,
+    screen_pixels: &mut [u8],
+    pitch: usize,
+    threads: usize,
+    workers: &mut Workers,
+) -> usize {
+    match scene.config.dithering {
+        Dithering::None => draw_frame::<P>(scene, screen_pixels, pitch, threads, workers),
+        Dithering::Bayer => {
+            draw_frame::<Dithered<P, Bayer>>(scene, screen_pixels, pitch, threads, workers)
+        }
+        Dithering::BlueNoise => {
+            draw_frame::<Dithered<P, BlueNoise>>(scene, screen_pixels, pitch, threads, workers)
+        }
+    }
+}
+
+fn draw_frame<P: Pixel>(
+    scene: &Scene,
+    screen_pixels: &mut [u8],
+    pitch: usize,
+    threads: usize,
+    workers: &mut Workers,
+) -> usize {
+    if threads == 1 {
+        let mut strip = Strip {
+            rows: screen_pixels
+                .chunks_mut(pitch)
+                .take(scene.screen_h as usize)
+                .collect(),
+            first_column: 0,
+        };
+        scene.draw_strip::<P>(&mut strip, 0..scene.screen_w)
+    } else {
+        draw_parallel::<P>(scene, screen_pixels, pitch, threads, workers)
+    }
+}
+
+/// Split the screen in strips of columns rendered each on their own thread of the pool, each one
+/// straight into its own part of every row of the screen
+fn draw_parallel<P: Pixel>(
+    scene: &Scene,
+    screen_pixels: &mut [u8],
+    pitch: usize,
+    threads: usize,
+    workers: &mut Workers,
+) -> usize {
+    let screen_w = scene.screen_w as usize;
+    let screen_h = scene.screen_h as usize;
+    let strip_w = screen_w.div_ceil(threads);
+
+    let mut strips: Vec<(Range<i32>, Strip)> = (0..screen_w)
+        .step_by(strip_w)
+        .map(|first| {
+            let last = min(first + strip_w, screen_w);
+            let strip = Strip {
+                rows: Vec::with_capacity(screen_h),
+                first_column: first,
+            };
+            (first as i32..last as i32, strip)
+        })
+        .collect();
+    for row in screen_pixels.chunks_mut(pitch).take(screen_h) {
+        let mut rest = row;
+        for (columns, strip) in strips.iter_mut() {
+            let (part, others) = rest.split_at_mut(columns.len() * P::BYTES);
+            strip.rows.push(part);
+            rest = others;
+        }
+    }
+
+    let tasks = strips
+        .into_iter()
+        .map(|(columns, mut strip)| -> Task {
+            Box::new(move || scene.draw_strip::<P>(&mut strip, columns))
+        })
+        .collect();
+    workers.run(tasks)
 }
@@ -0,0 +1,97 @@
+//! Lighting and fog of the palettized textures through tables, the way the colormaps of the
+//! classic Comanche did, instead of blending the channels of every pixel. The tables need the
+//! index of a single texel, so these textures are never filtered, whatever enable_filtering says.
+
+use super::blend_fog;
+use crate::fixed_int::FixedInt10;
+use crate::terrain::lighting::Lightmap;
+use crate::terrain::palette;
+use crate::terrain::palette::Palettized;
+use rgb::RGBA8;
+
+const FOG_LEVELS: i32 = palette::FOG_LEVELS as i32;
+
+/// Every color of the palette at every level of fog, 256 per level. The table follows the sky,
+/// it is kept with the other tables of the renderer until the palette or the sky changes.
+pub(super) fn fog_colors(palette: &[RGBA8], sky: RGBA8) -> Vec<RGBA8> {
+    (0..FOG_LEVELS)
+        .flat_map(|level| {
+            let sky_weight = FixedInt10::from(level) / (FOG_LEVELS - 1);
+            (0..256).map(move |i| match palette.get(i) {
+                Some(&color) => blend_fog(color, sky, sky_weight),
+                None => RGBA8::default(),
+            })
+        })
+        .collect()
+}
+
+pub(super) struct Colormap<'a> {
+    texture: &'a Palettized,
+    // Built by fog_colors from the palette of the texture
+    fog_colors: &'a [RGBA8],
+}
+
+impl<'a> Colormap<'a> {
+    pub(super) fn new(texture: &'a Palettized, fog_colors: &'a [RGBA8]) -> Colormap<'a> {
+        Colormap {
+            texture,
+            fog_colors,
+        }
+    }
+
+    /// Color of the texture at (x, y), lit and fogged
+    pub(super) fn color(
+        &self,
+        x: FixedInt10,
+        y: FixedInt10,
+        level: usize,
+        lightmap: Option<&Lightmap>,
+        fog: Option<FixedInt10>,
+    ) -> RGBA8 {
+        let index = self.texture.get_lod(x, y, level);
+        let index = match lightmap {
+            Some(lightmap) => self.texture.shade(index, lightmap.get_lod(x, y, level)),
+            None => index,
+        };
+        let fog_level = match fog {
+            Some(sky_weight) => i32::from((sky_weight * (FOG_LEVELS - 1)).round()) as usize,
+            None => 0,
+        };
+        self.fog_colors[fog_level << 8 | index as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixed_int::FixedInt10;
+    use crate::renderer::colormap::{fog_colors, Colormap};
+    use crate::terrain::Texture;
+    use lodepng::Bitmap;
+    use rgb::RGBA8;
+
+    #[test]
+    fn fog_levels() {
+        let color = RGBA8::new(200, 100, 0, 255);
+        let texture = Texture::from(Bitmap {
+            width: 2,
+            height: 2,
+            buffer: vec![color; 4],
+        })
+        .palettize();
+        let sky = RGBA8::new(0, 100, 200, 0);
+        let texture = texture.palettized().unwrap();
+        let fog_colors = fog_colors(texture.palette(), sky);
+        let colormap = Colormap::new(texture, &fog_colors);
+
+        let at = |fog| colormap.color(0.into(), 0.into(), 0, None, fog);
+        assert_eq!(at(None).rgb(), color.rgb());
+        assert_eq!(at(Some(FixedInt10::from(0))).rgb(), color.rgb());
+        let half = at(Some(FixedInt10::from(1) / 2));
+        assert!(
+            half.r.abs_diff(100) <= 4 && half.b.abs_diff(100) <= 4,
+            "{:?}",
+            half
+        );
+        assert_eq!(at(Some(FixedInt10::from_bits(1023))).rgb(), sky.rgb());
+    }
+}
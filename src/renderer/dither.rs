@@ -0,0 +1,99 @@
+//! Ordered dithering for the formats that drop bits of the colors. A threshold that depends on
+//! the position of the pixel is added before rounding down, so the smooth gradients of the fog
+//! and the sky turn into a fine pattern instead of bands.
+
+use super::pixel::Pixel;
+use rgb::RGBA8;
+use std::marker::PhantomData;
+
+pub(super) trait Dither {
+    /// Fraction of a step of the format, out of 256, added to the pixel at this position
+    fn threshold(column: usize, row: usize) -> u8;
+}
+
+/// The 4x4 Bayer matrix, regular and the cheapest
+pub(super) struct Bayer;
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Dither for Bayer {
+    fn threshold(column: usize, row: usize) -> u8 {
+        BAYER[row & 3][column & 3] * 16 + 8
+    }
+}
+
+/// A 16x16 tile of blue noise, without the visible crosshatch of Bayer
+pub(super) struct BlueNoise;
+
+// Ranks of the pixels in the void-and-cluster method, with a gaussian of 1.5 pixels
+#[rustfmt::skip]
+const BLUE_NOISE: [u8; 256] = [
+    120,  61, 134, 223,  84,  33, 168,  12, 113, 225,  63, 246, 185, 233,  88, 169,
+     23, 206, 181,  17, 109, 214,  58, 140, 201,  24, 161,  93,  34, 133,  14, 221,
+    144,  73, 250,  49, 158, 187,  81, 251, 100,  51, 142, 210, 172,  57, 191, 106,
+     42, 167, 101, 126, 220,   3, 121,  40, 170, 231,  82,   8, 114, 255,  80, 232,
+    212,  11, 195,  31,  72, 239, 152, 196,  16, 127, 188, 222,  45, 157,  26, 128,
+    154,  87, 235, 143, 179,  94,  54, 108, 237,  65,  29, 105, 139, 207, 184,  66,
+    248,  47, 115,  62, 209,  20, 164, 217,  79, 146, 178, 243,  69,  90,   0, 118,
+     30, 190, 173,   6, 131, 254,  41, 136,  10, 204,  43, 159,  22, 229, 162, 218,
+     77, 148,  99, 226,  74, 182, 117, 192,  86, 247, 119,  97, 197, 130,  53, 103,
+    242,  19, 198,  44, 155,  96,  59, 230,  28, 165,  60,   5, 240,  39, 175, 202,
+    137,  64, 122, 238,  25, 211,   1, 149, 104, 224, 135, 183, 151,  71, 112,   9,
+     91, 213, 166,  85, 186, 111, 249, 174,  48,  75, 208,  32,  89, 205, 236, 160,
+     37, 252,  18,  55, 138,  38,  78, 123, 194,  13, 107, 253, 124,  15,  56, 189,
+     76, 145, 110, 228, 203, 163, 219,  21, 241, 141, 171,  50, 156, 227, 102, 129,
+      2, 199, 176,  68,   7,  98,  52, 150,  92,  36, 215,  83, 200,  27, 177, 216,
+    244,  95,  35, 153, 245, 125, 193, 234,  70, 180, 132,   4, 116,  67, 147,  46,
+];
+
+impl Dither for BlueNoise {
+    fn threshold(column: usize, row: usize) -> u8 {
+        BLUE_NOISE[(row & 15) * 16 + (column & 15)]
+    }
+}
+
+/// The pixels of P, dithered with D
+pub(super) struct Dithered<P, D>(PhantomData<(P, D)>);
+
+impl<P: Pixel, D: Dither> Pixel for Dithered<P, D> {
+    const BYTES: usize = P::BYTES;
+    const DITHERED: bool = true;
+    type Encoded = P::Encoded;
+
+    fn encode(color: RGBA8, threshold: u8) -> P::Encoded {
+        P::encode(color, threshold)
+    }
+
+    fn threshold(column: usize, row: usize) -> u8 {
+        D::threshold(column, row)
+    }
+
+    fn write(pixel: &mut [u8], encoded: P::Encoded) {
+        P::write(pixel, encoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::dither::{Bayer, BlueNoise, Dither};
+
+    fn sorted_thresholds<D: Dither>(size: usize) -> Vec<u8> {
+        let mut thresholds: Vec<u8> = (0..size * size)
+            .map(|k| D::threshold(k % size, k / size))
+            .collect();
+        thresholds.sort_unstable();
+        thresholds
+    }
+
+    #[test]
+    fn every_level_once_per_tile() {
+        let bayer: Vec<u8> = (0..16).map(|level| level * 16 + 8).collect();
+        assert_eq!(sorted_thresholds::<Bayer>(4), bayer);
+        let blue_noise: Vec<u8> = (0..=255).collect();
+        assert_eq!(sorted_thresholds::<BlueNoise>(16), blue_noise);
+
+        // The pattern repeats across the screen
+        assert_eq!(Bayer::threshold(5, 6), Bayer::threshold(1, 2));
+        assert_eq!(BlueNoise::threshold(300, 17), BlueNoise::threshold(12, 1));
+    }
+}
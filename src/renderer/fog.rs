@@ -0,0 +1,143 @@
+//! Weight of the fog at each distance, in tables built with the configuration so that the loops
+//! only look it up
+
+use crate::config::{FogModel, HeightFogConfig, RendererConfig};
+use crate::fixed_int::FixedInt10;
+use rgb::RGBA8;
+
+// The thickness of the height fog is indexed by the integer part of the height, which goes from
+// 0 to 255 whatever the precision of the heightmap
+const HEIGHTS: usize = 256;
+
+/// What the fog between fog_start and distance_max is made of
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) struct DistanceFog {
+    start: i32,
+    model: FogModel,
+    density: f32,
+}
+
+impl DistanceFog {
+    pub(super) fn from_config(config: &RendererConfig) -> Option<DistanceFog> {
+        if config.fog {
+            Some(DistanceFog {
+                start: config.fog_start,
+                model: config.fog_model,
+                density: config.fog_density,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Weight of the sky for every z up to distance_max, 0 before fog_start. The exponential
+    /// models are scaled to reach only the sky at distance_max, so that the end of the view
+    /// blends into it.
+    pub(super) fn weights(&self, distance_max: i32) -> Vec<FixedInt10> {
+        let range = distance_max - self.start;
+        let curve = |distance: f32| match self.model {
+            FogModel::Linear => distance,
+            FogModel::Exponential => 1. - (-self.density * distance).exp(),
+            FogModel::ExponentialSquared => 1. - (-(self.density * distance).powi(2)).exp(),
+        };
+        let end = curve(range as f32);
+        (0..=distance_max)
+            .map(|z| {
+                if z <= self.start {
+                    0.into()
+                } else if self.model == FogModel::Linear {
+                    // Exactly the weight the renderer used to compute for each z
+                    FixedInt10::from(z - self.start) / range
+                } else {
+                    FixedInt10::from(curve((z - self.start) as f32) / end)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Fog lying in the valleys, the thickest at the bottom of the map and gone at its top height
+pub(super) struct HeightFog {
+    pub(super) color: RGBA8,
+    // Weight of the fog for every z, at the bottom of the map
+    weights: Vec<FixedInt10>,
+    // How much of it there is at each height of the terrain
+    thickness: Vec<FixedInt10>,
+}
+
+impl HeightFog {
+    pub(super) fn new(config: &HeightFogConfig, distance_max: i32) -> HeightFog {
+        let weights = (0..=distance_max)
+            .map(|z| FixedInt10::from(1. - (-config.density * z as f32).exp()))
+            .collect();
+        let thickness = (0..HEIGHTS)
+            .map(|h| FixedInt10::from(((config.height - h as f32) / config.height).clamp(0., 1.)))
+            .collect();
+        HeightFog {
+            color: RGBA8::new(config.color[0], config.color[1], config.color[2], 0),
+            weights,
+            thickness,
+        }
+    }
+
+    /// Weight of the fog on the terrain at the given height, seen from a distance z
+    pub(super) fn weight(&self, z: i32, height: FixedInt10) -> FixedInt10 {
+        let height = i32::from(height).clamp(0, HEIGHTS as i32 - 1);
+        self.weights[z as usize] * self.thickness[height as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{FogModel, HeightFogConfig};
+    use crate::fixed_int::FixedInt10;
+    use crate::renderer::fog::{DistanceFog, HeightFog};
+
+    fn weights(model: FogModel) -> Vec<FixedInt10> {
+        DistanceFog {
+            start: 100,
+            model,
+            density: 0.01,
+        }
+        .weights(300)
+    }
+
+    #[test]
+    fn distance_models() {
+        let linear = weights(FogModel::Linear);
+        assert_eq!(linear.len(), 301);
+        assert_eq!(linear[100], 0.into());
+        assert_eq!(linear[200], FixedInt10::from(1) / 2);
+        assert_eq!(linear[300], 1.into());
+
+        for &model in &[FogModel::Exponential, FogModel::ExponentialSquared] {
+            let weights = weights(model);
+            assert_eq!(weights[100], 0.into());
+            assert_eq!(weights[300], 1.into());
+            assert!(weights.windows(2).all(|w| w[0] <= w[1]), "{:?}", model);
+        }
+        // Exponential fog thickens the fastest right after fog_start, squared fog the slowest
+        let exponential = weights(FogModel::Exponential);
+        let squared = weights(FogModel::ExponentialSquared);
+        assert!(squared[150] < linear[150] && linear[150] < exponential[150]);
+    }
+
+    #[test]
+    fn height_fog() {
+        let fog = HeightFog::new(
+            &HeightFogConfig {
+                height: 100.,
+                density: 0.01,
+                color: [255, 255, 255],
+            },
+            300,
+        );
+        assert_eq!(fog.weight(0, 0.into()), 0.into());
+        assert_eq!(fog.weight(200, 100.into()), 0.into());
+        assert_eq!(fog.weight(200, 255.into()), 0.into());
+        // Thicker at the bottom of the valleys and further away
+        assert!(fog.weight(200, 20.into()) > fog.weight(200, 60.into()));
+        assert!(fog.weight(200, 20.into()) > fog.weight(50, 20.into()));
+        assert!(fog.weight(300, 0.into()) < 1.into());
+    }
+}
@@ -0,0 +1,167 @@
+//! Rendering path without any float or division in the loops, for the CPUs without an FPU. What
+//! depends on the camera is computed once per strip, the view is then stepped through with
+//! additions and shifts, and the projection multiplies by a table of 1/z.
+
+use super::{draw_line, lod_level, Pixel, Scene, Strip};
+use crate::fixed_int::{Fixed, FixedInt10};
+use crate::vector::Vector2;
+use std::cmp::{max, min};
+use std::ops::Range;
+
+// Positions relative to the camera
+type Position = Fixed<16>;
+const POSITION_BITS: i32 = Position::FRACTION_BITS;
+// The step between two columns is added across the whole screen, so it needs more precision
+type Stride = Fixed<24>;
+const STRIDE_BITS: i32 = Stride::FRACTION_BITS;
+// Fractional bits of the factors in the table of reciprocals
+const RECIPROCAL_BITS: i32 = 16;
+// Fractional bits of the width of a column, which is small and multiplied by the whole screen
+const COLUMN_BITS: i32 = 32;
+
+/// The width of the view at a distance of 1, in fixed point, built with the other tables so that
+/// the strips need neither float nor division
+#[derive(Clone, Copy)]
+pub(super) struct Frustum {
+    // Half of the width of the view
+    half_width: i64,
+    // Width of a column
+    column_width: i64,
+}
+
+impl Frustum {
+    pub(super) fn new(tan_half_fov: f32, screen_w: i32) -> Frustum {
+        Frustum {
+            half_width: (tan_half_fov * (1 << POSITION_BITS) as f32).round() as i64,
+            column_width: (2. * tan_half_fov as f64 / screen_w as f64
+                * (1i64 << COLUMN_BITS) as f64)
+                .round() as i64,
+        }
+    }
+}
+
+/// scale_height / z for every z up to distance_max, in fixed point
+pub(super) fn reciprocals(scale_height: i32, distance_max: i32) -> Vec<i64> {
+    (0..max(distance_max, 1) as i64)
+        .map(|z| match z {
+            0 => 0,
+            z => ((scale_height as i64) << RECIPROCAL_BITS) / z,
+        })
+        .collect()
+}
+
+impl<'a> Scene<'a> {
+    pub(super) fn draw_strip_integer<P: Pixel>(
+        &self,
+        strip: &mut Strip,
+        columns: Range<i32>,
+    ) -> usize {
+        let (camera, config) = (self.camera, self.config);
+        let screen_h = self.screen_h;
+
+        let cos = camera.cos_angle.to_bits() as i64;
+        let sin = camera.sin_angle.to_bits() as i64;
+        let Frustum {
+            half_width,
+            column_width,
+        } = self.tables.frustum;
+
+        // The left edge of the view and the step between two columns both grow linearly with z,
+        // here they are at a distance of 1
+        let left_step = Vector2 {
+            x: Position::from_bits((-sin - ((cos * half_width) >> POSITION_BITS)) as i32),
+            y: Position::from_bits((-cos + ((sin * half_width) >> POSITION_BITS)) as i32),
+        };
+        let stride_shift = POSITION_BITS + COLUMN_BITS - STRIDE_BITS;
+        let stride_step = Vector2 {
+            x: Stride::from_bits(((cos * column_width) >> stride_shift) as i32),
+            y: Stride::from_bits(-((sin * column_width) >> stride_shift) as i32),
+        };
+        let reciprocals = &self.tables.reciprocals;
+
+        let mut max_height = vec![0; columns.len()];
+        // Columns that still have pixels to fill, there is nothing left to draw when none are
+        let mut open_columns = columns.len();
+        let mut z_steps = 0;
+
+        let mut z = 1;
+        let mut left = left_step;
+        let mut stride = stride_step;
+        while z < config.distance_max && open_columns > 0 {
+            let level = if config.enable_lod {
+                lod_level(z, config.lod_distance, self.levels)
+            } else {
+                0
+            };
+            let fog = self.fog_weight(z);
+            let reciprocal = reciprocals[z as usize];
+
+            let column_stride: Vector2<Position> = Vector2 {
+                x: stride.x.convert(),
+                y: stride.y.convert(),
+            };
+            let mut position = Vector2 {
+                x: left.x + column_stride.x * columns.start,
+                y: left.y + column_stride.y * columns.start,
+            };
+
+            for i in columns.clone() {
+                let column = (i - columns.start) as usize;
+                let x = camera.x + position.x.convert();
+                let y = camera.y + position.y.convert();
+                position.x += column_stride.x;
+                position.y += column_stride.y;
+                if max_height[column] >= screen_h {
+                    continue;
+                }
+
+                let height = self.height(x, y, z, level);
+                let relative_height = (height - camera.z).to_bits() as i64;
+                let real_height = FixedInt10::from_bits(
+                    ((relative_height * reciprocal) >> RECIPROCAL_BITS) as i32,
+                ) + self.horizons[i as usize];
+
+                let real_height: i32 = max(0, real_height.into());
+
+                if real_height > max_height[column] {
+                    draw_line::<P>(
+                        strip,
+                        column,
+                        max_height[column] as usize,
+                        min(real_height, screen_h) as usize,
+                        self.color(x, y, z, height, level, fog),
+                    );
+                    max_height[column] = real_height;
+                    if real_height >= screen_h {
+                        open_columns -= 1;
+                    }
+                }
+            }
+
+            // Each level is twice as coarse as the previous one, so are the steps
+            z += 1 << level;
+            left.x += left_step.x << level as i32;
+            left.y += left_step.y << level as i32;
+            stride.x += stride_step.x << level as i32;
+            stride.y += stride_step.y << level as i32;
+            z_steps += 1;
+        }
+
+        self.draw_sky::<P>(strip, &max_height);
+        z_steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::integer::{reciprocals, RECIPROCAL_BITS};
+
+    #[test]
+    fn reciprocal_table() {
+        let table = reciprocals(120, 300);
+        assert_eq!(table.len(), 300);
+        assert_eq!(table[1], 120 << RECIPROCAL_BITS);
+        assert_eq!(table[120], 1 << RECIPROCAL_BITS);
+        assert_eq!(table[240], 1 << (RECIPROCAL_BITS - 1));
+    }
+}
@@ -0,0 +1,116 @@
+//! How a color is written for each pixel format. Every format is a type of its own, so that the
+//! drawing loops are compiled once per format and never look at it per pixel.
+
+use rgb::RGBA8;
+
+pub(super) trait Pixel {
+    const BYTES: usize;
+    /// Whether each pixel has its own threshold, otherwise the color is converted once per line
+    const DITHERED: bool = false;
+    /// The color converted, ready to be copied in the pixels
+    type Encoded: Copy;
+
+    /// Add threshold / 256 of a step of the format to the color, then drop the bits that do not
+    /// fit
+    fn encode(color: RGBA8, threshold: u8) -> Self::Encoded;
+
+    fn threshold(_column: usize, _row: usize) -> u8 {
+        0
+    }
+
+    fn write(pixel: &mut [u8], encoded: Self::Encoded);
+}
+
+/// 32 bits pixels, with the red, green and blue bytes at the given offsets. The fourth byte is
+/// left untouched, and there is no bit to dither.
+pub(super) struct Bytes<const R: usize, const G: usize, const B: usize>;
+
+impl<const R: usize, const G: usize, const B: usize> Pixel for Bytes<R, G, B> {
+    const BYTES: usize = 4;
+    type Encoded = RGBA8;
+
+    fn encode(color: RGBA8, _threshold: u8) -> RGBA8 {
+        color
+    }
+
+    fn write(pixel: &mut [u8], color: RGBA8) {
+        pixel[R] = color.r;
+        pixel[G] = color.g;
+        pixel[B] = color.b;
+    }
+}
+
+pub(super) type XrgbBytes = Bytes<1, 2, 3>;
+pub(super) type BgrxBytes = Bytes<2, 1, 0>;
+pub(super) type RgbaBytes = Bytes<0, 1, 2>;
+
+/// 16 bits native-endian pixels, with 5 bits of red and blue and GREEN bits of green
+pub(super) struct Packed<const GREEN: u32>;
+
+impl<const GREEN: u32> Pixel for Packed<GREEN> {
+    const BYTES: usize = 2;
+    type Encoded = [u8; 2];
+
+    fn encode(color: RGBA8, threshold: u8) -> [u8; 2] {
+        // Keep the given number of bits of a channel
+        let channel =
+            |value: u8, bits: u32| (value.saturating_add(threshold >> bits) >> (8 - bits)) as u16;
+        let r = channel(color.r, 5);
+        let g = channel(color.g, GREEN);
+        let b = channel(color.b, 5);
+        ((r << (5 + GREEN)) | (g << 5) | b).to_ne_bytes()
+    }
+
+    fn write(pixel: &mut [u8], encoded: [u8; 2]) {
+        pixel[0] = encoded[0];
+        pixel[1] = encoded[1];
+    }
+}
+
+pub(super) type Rgb565 = Packed<6>;
+pub(super) type Rgb555 = Packed<5>;
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::pixel::{Pixel, Rgb555, Rgb565, XrgbBytes};
+    use rgb::RGBA8;
+
+    fn packed(color: RGBA8, encode: fn(RGBA8, u8) -> [u8; 2]) -> u16 {
+        u16::from_ne_bytes(encode(color, 0))
+    }
+
+    #[test]
+    fn packed_formats() {
+        let white = RGBA8::new(255, 255, 255, 0);
+        assert_eq!(packed(white, Rgb565::encode), 0xffff);
+        assert_eq!(packed(white, Rgb555::encode), 0x7fff);
+
+        let color = RGBA8::new(0xf8, 0x84, 0x10, 0);
+        assert_eq!(
+            packed(color, Rgb565::encode),
+            (0x1f << 11) | (0x21 << 5) | 0x02
+        );
+        assert_eq!(
+            packed(color, Rgb555::encode),
+            (0x1f << 10) | (0x10 << 5) | 0x02
+        );
+    }
+
+    #[test]
+    fn untouched_padding() {
+        let mut pixel = [7; 4];
+        XrgbBytes::write(&mut pixel, XrgbBytes::encode(RGBA8::new(1, 2, 3, 4), 255));
+        assert_eq!(pixel, [7, 1, 2, 3]);
+    }
+
+    #[test]
+    fn thresholds() {
+        let color = RGBA8::new(0xf8 + 3, 0x84 + 3, 0x10 + 4, 0);
+        let dithered = |threshold| u16::from_ne_bytes(Rgb565::encode(color, threshold));
+        // Red and blue have steps of 8, green of 4
+        assert_eq!(dithered(0), (0x1f << 11) | (0x21 << 5) | 0x02);
+        assert_eq!(dithered(128), (0x1f << 11) | (0x22 << 5) | 0x03);
+        // The red channel saturates instead of wrapping around
+        assert_eq!(dithered(255), (0x1f << 11) | (0x22 << 5) | 0x03);
+    }
+}
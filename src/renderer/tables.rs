@@ -0,0 +1,161 @@
+//! Everything the renderer derives from the configuration, built when it changes instead of on
+//! every frame. Most of it needs floats or divisions, which the CPUs without an FPU pay dearly.
+
+use super::colormap::fog_colors;
+use super::fog::{DistanceFog, HeightFog};
+use super::integer::{reciprocals, Frustum};
+use crate::config::{HeightFogConfig, RendererConfig};
+use crate::fixed_int::FixedInt10;
+use rgb::RGBA8;
+
+/// The values the tables are built from, the tables are rebuilt when any of them changes
+#[derive(Clone, PartialEq)]
+pub(super) struct Key {
+    screen_w: i32,
+    fov: f32,
+    scale_height: f32,
+    distance_max: i32,
+    fog: Option<DistanceFog>,
+    height_fog: Option<HeightFogConfig>,
+    fog_color: RGBA8,
+}
+
+impl Key {
+    pub(super) fn new(config: &RendererConfig, screen_w: i32, fog_color: RGBA8) -> Key {
+        Key {
+            screen_w,
+            fov: config.fov,
+            scale_height: config.scale_height,
+            distance_max: config.distance_max,
+            fog: DistanceFog::from_config(config),
+            height_fog: config.height_fog,
+            fog_color,
+        }
+    }
+}
+
+pub(super) struct Tables {
+    key: Key,
+    // Palette of the texture the colormap was built for, empty for RGBA textures
+    palette: Vec<RGBA8>,
+    // Half of the width of the view at a distance of 1
+    pub(super) tan_half_fov: f32,
+    // Projection factor of the heights, the focal length in pixels times scale_height
+    pub(super) scale_height: i32,
+    // The same view and scale_height / z for every z, for the integer path
+    pub(super) frustum: Frustum,
+    pub(super) reciprocals: Vec<i64>,
+    // Weight of the sky for each z, empty without fog
+    pub(super) fog_weights: Vec<FixedInt10>,
+    pub(super) height_fog: Option<HeightFog>,
+    // Colors of the palette at every level of fog, empty for RGBA textures
+    pub(super) fog_colors: Vec<RGBA8>,
+}
+
+impl Tables {
+    pub(super) fn new(key: Key, palette: &[RGBA8]) -> Tables {
+        let tan_half_fov = (key.fov.to_radians() / 2.).tan();
+        // The vertical scale follows the horizontal one, whatever the aspect ratio
+        let focal = (key.screen_w / 2) as f32 / tan_half_fov;
+        let scale_height = (focal * key.scale_height) as i32;
+        Tables {
+            tan_half_fov,
+            scale_height,
+            frustum: Frustum::new(tan_half_fov, key.screen_w),
+            reciprocals: reciprocals(scale_height, key.distance_max),
+            fog_weights: key
+                .fog
+                .map_or(Vec::new(), |fog| fog.weights(key.distance_max)),
+            height_fog: key
+                .height_fog
+                .map(|height_fog| HeightFog::new(&height_fog, key.distance_max)),
+            fog_colors: if palette.is_empty() {
+                Vec::new()
+            } else {
+                fog_colors(palette, key.fog_color)
+            },
+            palette: palette.to_vec(),
+            key,
+        }
+    }
+
+    /// Keep the tables when they were built from the same values and palette, otherwise rebuild
+    /// them
+    pub(super) fn update<'t>(
+        tables: &'t mut Option<Tables>,
+        key: Key,
+        palette: &[RGBA8],
+    ) -> &'t Tables {
+        if tables
+            .as_ref()
+            .is_none_or(|t| t.key != key || t.palette != palette)
+        {
+            *tables = Some(Tables::new(key, palette));
+        }
+        tables.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::tables::{Key, Tables};
+    use rgb::RGBA8;
+
+    fn key(fov: f32) -> Key {
+        Key {
+            screen_w: 320,
+            fov,
+            scale_height: 1.,
+            distance_max: 300,
+            fog: None,
+            height_fog: None,
+            fog_color: RGBA8::new(80, 120, 250, 0),
+        }
+    }
+
+    #[test]
+    fn rebuilt_on_change() {
+        let mut tables = None;
+        let first = Tables::update(&mut tables, key(90.), &[])
+            .reciprocals
+            .as_ptr();
+        assert_eq!(
+            Tables::update(&mut tables, key(90.), &[])
+                .reciprocals
+                .as_ptr(),
+            first
+        );
+
+        let zoomed = Tables::update(&mut tables, key(45.), &[]);
+        assert!(zoomed.tan_half_fov < 0.5);
+        // Half of the 320 pixels over tan(22.5°)
+        assert_eq!(zoomed.scale_height, 386);
+        assert_eq!(zoomed.reciprocals[1], 386 << 16);
+        // Without fog, there is no weight to look up
+        assert!(zoomed.fog_weights.is_empty());
+        assert!(zoomed.height_fog.is_none());
+        assert!(zoomed.fog_colors.is_empty());
+
+        // The colormap follows the palette and the fog color
+        let palette = [RGBA8::new(200, 100, 0, 255)];
+        let colors = Tables::update(&mut tables, key(45.), &palette)
+            .fog_colors
+            .as_ptr();
+        assert_eq!(
+            Tables::update(&mut tables, key(45.), &palette)
+                .fog_colors
+                .as_ptr(),
+            colors
+        );
+        let red = Key {
+            fog_color: RGBA8::new(255, 0, 0, 0),
+            ..key(45.)
+        };
+        let fogged = Tables::update(&mut tables, red, &palette);
+        assert_eq!(fogged.fog_colors.len(), 32 * 256);
+        assert_eq!(
+            fogged.fog_colors[31 << 8].rgb(),
+            RGBA8::new(255, 0, 0, 0).rgb()
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Threads kept from one frame to the next to render the strips of the screen. Spawning and
+//! joining them on every frame cost about 25µs per thread on a single core machine, the pool only
+//! pays for sending each one a job.
+
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, SendError, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Renders a strip and returns its number of steps in z, borrowing the frame for 's
+pub(super) type Task<'s> = Box<dyn FnOnce() -> usize + Send + 's>;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+struct Worker {
+    jobs: Sender<Job>,
+    handle: JoinHandle<()>,
+}
+
+/// The pool grows to the largest number of tasks run at once, the idle workers wait on their
+/// channel until the renderer is dropped
+#[derive(Default)]
+pub(super) struct Workers {
+    workers: Vec<Worker>,
+}
+
+impl Workers {
+    /// Run the first task on the calling thread and the others on the workers, and return the sum
+    /// of their results once all of them are done. A panic in any task is raised again here.
+    pub(super) fn run(&mut self, tasks: Vec<Task>) -> usize {
+        let mut tasks = tasks.into_iter();
+        let first = match tasks.next() {
+            Some(task) => task,
+            None => return 0,
+        };
+        // Spawn before sending any job, a failure to spawn must not leave a job running
+        while self.workers.len() < tasks.len() {
+            let (jobs, receiver) = mpsc::channel::<Job>();
+            let handle = thread::spawn(move || {
+                for job in receiver {
+                    job();
+                }
+            });
+            self.workers.push(Worker { jobs, handle });
+        }
+
+        let (done, results) = mpsc::channel::<Result<usize, Box<dyn Any + Send>>>();
+        for (worker, task) in self.workers.iter().zip(tasks) {
+            let done = done.clone();
+            let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                let _ = done.send(panic::catch_unwind(AssertUnwindSafe(task)));
+            });
+            // SAFETY: the borrows of the job outlive this call, which does not return or unwind
+            // before every job has been run or dropped: results only ends once all the clones of
+            // done, which each job owns, are gone. Nothing below can panic before that.
+            let job: Job = unsafe { mem::transmute(job) };
+            if let Err(SendError(job)) = worker.jobs.send(job) {
+                job();
+            }
+        }
+        drop(done);
+
+        let mine = panic::catch_unwind(AssertUnwindSafe(first));
+        let mut z_steps = 0;
+        let mut panicked = None;
+        for result in std::iter::once(mine).chain(results) {
+            match result {
+                Ok(steps) => z_steps += steps,
+                Err(payload) => panicked = panicked.or(Some(payload)),
+            }
+        }
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        z_steps
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        for Worker { jobs, handle } in self.workers.drain(..) {
+            // Closing the channel ends the loop of the worker
+            drop(jobs);
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Task, Workers};
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn reused() {
+        let mut workers = Workers::default();
+        let mut columns = [0; 8];
+        for frame in 1..4 {
+            let tasks: Vec<Task> = columns
+                .chunks_mut(2)
+                .map(|strip| -> Task {
+                    Box::new(move || {
+                        strip.iter_mut().for_each(|c| *c += 1);
+                        strip.len()
+                    })
+                })
+                .collect();
+            assert_eq!(workers.run(tasks), 8);
+            assert!(columns.iter().all(|&c| c == frame));
+            assert_eq!(workers.workers.len(), 3);
+        }
+    }
+
+    #[test]
+    fn panic() {
+        let mut workers = Workers::default();
+        let tasks: Vec<Task> = vec![Box::new(|| 1), Box::new(|| panic!("strip"))];
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| workers.run(tasks))).is_err());
+        // The worker survived the panic
+        let tasks: Vec<Task> = vec![Box::new(|| 1), Box::new(|| 2)];
+        assert_eq!(workers.run(tasks), 3);
+    }
+}
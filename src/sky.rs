@@ -0,0 +1,198 @@
+//! What is drawn above the terrain: a single color, a gradient starting on the horizon, or a
+//! panorama that turns with the camera.
+
+use crate::config::{SkyConfig, SkyMode};
+use crate::fixed_int::FixedInt10;
+use lodepng::{Bitmap, ColorType};
+use rgb::RGBA8;
+
+#[derive(Clone)]
+pub struct Sky {
+    background: Background,
+    fog_color: RGBA8,
+}
+
+#[derive(Clone)]
+pub(crate) enum Background {
+    Color(RGBA8),
+    // From the horizon to a screen height above it
+    Gradient(Vec<RGBA8>),
+    Panorama(Panorama),
+}
+
+/// A cylindrical image covering a full turn, with its bottom row on the horizon
+#[derive(Clone)]
+pub(crate) struct Panorama {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pixels: Vec<RGBA8>,
+}
+
+impl Panorama {
+    /// Wrap around the turn, and repeat the bottom and top rows below and above the image
+    pub(crate) fn at(&self, u: i32, v: i32) -> RGBA8 {
+        let u = u.rem_euclid(self.width as i32) as usize;
+        let v = v.clamp(0, self.height as i32 - 1) as usize;
+        self.pixels[u + v * self.width]
+    }
+}
+
+fn to_rgba(color: [u8; 3]) -> RGBA8 {
+    RGBA8::new(color[0], color[1], color[2], 0)
+}
+
+impl Default for Sky {
+    fn default() -> Sky {
+        Sky::color(RGBA8::new(80, 120, 250, 0))
+    }
+}
+
+impl Sky {
+    pub fn color(color: RGBA8) -> Sky {
+        Sky {
+            background: Background::Color(color),
+            fog_color: color,
+        }
+    }
+
+    /// Colors from the horizon up to a screen height above it
+    pub fn gradient(colors: &[RGBA8]) -> Result<Sky, String> {
+        if colors.len() < 2 || colors.len() > 3 {
+            return Err(format!(
+                "A sky gradient needs 2 or 3 colors, not {}",
+                colors.len()
+            ));
+        }
+        Ok(Sky {
+            background: Background::Gradient(colors.to_vec()),
+            fog_color: colors[0],
+        })
+    }
+
+    /// The far terrain fades into the average color of the bottom row
+    pub fn panorama(image: Bitmap<RGBA8>) -> Result<Sky, String> {
+        if image.width == 0 || image.height == 0 || image.buffer.len() != image.width * image.height
+        {
+            return Err(format!(
+                "Invalid panorama size {}x{} for {} pixels",
+                image.width,
+                image.height,
+                image.buffer.len()
+            ));
+        }
+        let bottom = &image.buffer[(image.height - 1) * image.width..];
+        let average = |channel: fn(&RGBA8) -> u8| {
+            (bottom.iter().map(|p| channel(p) as usize).sum::<usize>() / bottom.len()) as u8
+        };
+        let fog_color = RGBA8::new(average(|p| p.r), average(|p| p.g), average(|p| p.b), 0);
+        Ok(Sky {
+            background: Background::Panorama(Panorama {
+                width: image.width,
+                height: image.height,
+                pixels: image.buffer,
+            }),
+            fog_color,
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<Sky, String> {
+        match lodepng::decode_file(path, ColorType::RGBA, 8) {
+            Err(e) => Err(format!("Error opening the file {} ({})", path, e)),
+            Ok(lodepng::Image::RGBA(image)) => Sky::panorama(image),
+            Ok(image) => Err(format!(
+                "Not the right format, expect RGBA 8 bits. Was {:?}",
+                image
+            )),
+        }
+    }
+
+    pub fn from_config(config: &SkyConfig) -> Result<Sky, String> {
+        let sky = match &config.mode {
+            SkyMode::Color { color } => Sky::color(to_rgba(*color)),
+            SkyMode::Gradient { colors } => {
+                Sky::gradient(&colors.iter().map(|&c| to_rgba(c)).collect::<Vec<_>>())?
+            }
+            SkyMode::Panorama { image } => Sky::from_file(image)?,
+        };
+        Ok(match config.fog_color {
+            Some(color) => sky.with_fog_color(to_rgba(color)),
+            None => sky,
+        })
+    }
+
+    /// Fade the far terrain into another color than the one of the sky on the horizon
+    pub fn with_fog_color(self, fog_color: RGBA8) -> Sky {
+        Sky { fog_color, ..self }
+    }
+
+    pub fn fog_color(&self) -> RGBA8 {
+        self.fog_color
+    }
+
+    pub(crate) fn background(&self) -> &Background {
+        &self.background
+    }
+}
+
+/// Color of the gradient at t, from 0 on the horizon to 1 on the last color
+pub(crate) fn gradient_color(colors: &[RGBA8], t: FixedInt10) -> RGBA8 {
+    let segments = colors.len() as i32 - 1;
+    let position = t * segments;
+    let segment = i32::from(position).clamp(0, segments - 1);
+    let t = position - segment;
+    let (from, to) = (colors[segment as usize], colors[segment as usize + 1]);
+    let channel = |from: u8, to: u8| -> u8 {
+        i32::from(FixedInt10::from(from).lerp(to.into(), t).round()) as u8
+    };
+    RGBA8::new(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+        0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixed_int::FixedInt10;
+    use crate::sky::{gradient_color, Sky};
+    use lodepng::Bitmap;
+    use rgb::RGBA8;
+
+    #[test]
+    fn gradient() {
+        let colors = [
+            RGBA8::new(200, 200, 200, 0),
+            RGBA8::new(100, 100, 250, 0),
+            RGBA8::new(0, 0, 100, 0),
+        ];
+        assert_eq!(gradient_color(&colors, 0.into()), colors[0]);
+        assert_eq!(
+            gradient_color(&colors, FixedInt10::from(1) / 4),
+            RGBA8::new(150, 150, 225, 0)
+        );
+        assert_eq!(gradient_color(&colors, FixedInt10::from(1) / 2), colors[1]);
+        assert_eq!(gradient_color(&colors, 1.into()), colors[2]);
+
+        assert!(Sky::gradient(&colors[..1]).is_err());
+        assert_eq!(Sky::gradient(&colors).unwrap().fog_color(), colors[0]);
+    }
+
+    #[test]
+    fn panorama() {
+        let sky = Sky::panorama(Bitmap {
+            width: 2,
+            height: 2,
+            buffer: vec![
+                RGBA8::new(0, 0, 0, 255),
+                RGBA8::new(0, 0, 0, 255),
+                RGBA8::new(100, 0, 50, 255),
+                RGBA8::new(200, 0, 50, 255),
+            ],
+        })
+        .unwrap();
+        assert_eq!(sky.fog_color(), RGBA8::new(150, 0, 50, 0));
+        let red = RGBA8::new(255, 0, 0, 0);
+        assert_eq!(sky.with_fog_color(red).fog_color(), red);
+    }
+}
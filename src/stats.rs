@@ -41,6 +41,10 @@ impl Stats {
         self.num_events += 1;
     }
 
+    pub fn num_events(&self) -> u32 {
+        self.num_events
+    }
+
     pub fn avg_micro(&self) -> f32 {
         self.total_time.as_micros() as f32 / self.num_events as f32
     }
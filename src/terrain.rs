@@ -2,124 +2,325 @@ use crate::fixed_int::FixedInt10;
 use lodepng::{Bitmap, ColorType, Grey};
 use rgb::{RGBA, RGBA8};
 
-pub struct HeightMap(Bitmap<Grey<u8>>);
+pub mod generate;
+pub mod lighting;
+pub mod palette;
 
-pub struct Texture(Bitmap<RGBA8>);
+use palette::Palettized;
 
-// We hardcode a size of 1024 for the heightmap / texture
+// Each level of the mip chain is half the size of the previous one, level 0 is the original image
+// Heights are stored in 1/256th of the unit, so that 16 bits heightmaps keep their precision
+pub struct HeightMap {
+    levels: Vec<Level<u16>>,
+}
+
+pub struct Texture {
+    texels: Texels,
+}
+
+enum Texels {
+    Rgba(Vec<Level<RGBA8>>),
+    // A quarter of the memory, at the cost of the colors the palette does not have
+    Palettized(Palettized),
+}
+
+/// What to return for coordinates outside of the image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Border<T> {
+    /// Tile the image forever
+    Wrap,
+    /// Repeat the pixels on the edge of the image
+    Clamp,
+    /// Return a fixed value, to get an island in the middle of the sea
+    Constant(T),
+}
+
+/// One image of the mip chain
+struct Level<T> {
+    width: usize,
+    height: usize,
+    data: Vec<T>,
+    border: Border<T>,
+    // Size of the base level of the mip chain, and of this level relative to it on SCALE_BITS
+    // fractional bits
+    base_size: (usize, usize),
+    scale: (i64, i64),
+}
+
+// Enough bits for the scales to be exact when the size is a power of two, and for the rounding
+// of the others to never reach the next texel
+const SCALE_BITS: i32 = 30;
+
+// With a cheap mask when the size is a power of two and a modulo otherwise
+fn wrap(coord: i32, size: usize) -> usize {
+    if size.is_power_of_two() {
+        coord as usize & (size - 1)
+    } else {
+        coord.rem_euclid(size as i32) as usize
+    }
+}
+
+fn clamp(coord: i32, size: usize) -> usize {
+    coord.max(0).min(size as i32 - 1) as usize
+}
+
+impl<T: Copy> Level<T> {
+    /// A base level, at the scale of the map
+    fn new(width: usize, height: usize, data: Vec<T>, border: Border<T>) -> Level<T> {
+        Level {
+            width,
+            height,
+            data,
+            border,
+            base_size: (width, height),
+            scale: (1 << SCALE_BITS, 1 << SCALE_BITS),
+        }
+    }
+
+    /// Texel at the coordinates (i, j) of the base level. The border applies to the base level,
+    /// so that every level covers the same extent and wraps with the same period whatever the
+    /// size of the map.
+    fn sample(&self, i: FixedInt10, j: FixedInt10) -> T {
+        let (i, j): (i32, i32) = (i.into(), j.into());
+        let (width, height) = self.base_size;
+        let (i, j) = match self.border {
+            Border::Wrap => (wrap(i, width), wrap(j, height)),
+            Border::Clamp => (clamp(i, width), clamp(j, height)),
+            Border::Constant(value) => {
+                if i < 0 || j < 0 || i >= width as i32 || j >= height as i32 {
+                    return value;
+                }
+                (i as usize, j as usize)
+            }
+        };
+        let i = (i as i64 * self.scale.0) >> SCALE_BITS;
+        let j = (j as i64 * self.scale.1) >> SCALE_BITS;
+        self.data[i as usize + self.width * j as usize]
+    }
+
+    fn at(&self, i: i32, j: i32) -> T {
+        match self.border {
+            Border::Wrap => self.data[wrap(i, self.width) + self.width * wrap(j, self.height)],
+            Border::Clamp => self.data[clamp(i, self.width) + self.width * clamp(j, self.height)],
+            Border::Constant(value) => {
+                if i < 0 || j < 0 || i >= self.width as i32 || j >= self.height as i32 {
+                    value
+                } else {
+                    self.data[i as usize + self.width * j as usize]
+                }
+            }
+        }
+    }
+
+    /// Halve the size of the image by averaging blocks of 2x2 pixels. With an odd size the size
+    /// is rounded up, and the last row or column is averaged with itself.
+    fn downsample(&self, average: fn([T; 4]) -> T) -> Level<T> {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut data = Vec::with_capacity(width * height);
+        for j in 0..height {
+            let top = 2 * j * self.width;
+            let bottom = (2 * j + 1).min(self.height - 1) * self.width;
+            for i in 0..width {
+                let left = 2 * i;
+                let right = (2 * i + 1).min(self.width - 1);
+                data.push(average([
+                    self.data[left + top],
+                    self.data[right + top],
+                    self.data[left + bottom],
+                    self.data[right + bottom],
+                ]));
+            }
+        }
+        Level {
+            width,
+            height,
+            data,
+            border: self.border,
+            base_size: self.base_size,
+            // Rounded up, so that a texel of the base level is never counted in the level before
+            // the one it belongs to
+            scale: (
+                (((width as u64) << SCALE_BITS).div_ceil(self.base_size.0 as u64)) as i64,
+                (((height as u64) << SCALE_BITS).div_ceil(self.base_size.1 as u64)) as i64,
+            ),
+        }
+    }
+}
+
+/// Build all the levels of the mip chain, down to a 1x1 image
+fn mip_chain<T: Copy>(base: Level<T>, average: fn([T; 4]) -> T) -> Vec<Level<T>> {
+    let mut levels = vec![base];
+    loop {
+        let previous = &levels[levels.len() - 1];
+        if previous.width == 1 && previous.height == 1 {
+            return levels;
+        }
+        let next = previous.downsample(average);
+        levels.push(next);
+    }
+}
+
+fn average_u8(values: [u8; 4]) -> u8 {
+    ((values.iter().map(|&v| v as u16).sum::<u16>() + 2) / 4) as u8
+}
+
+fn average_u16(values: [u16; 4]) -> u16 {
+    ((values.iter().map(|&v| v as u32).sum::<u32>() + 2) / 4) as u16
+}
+
+fn average_rgba(values: [RGBA8; 4]) -> RGBA8 {
+    RGBA8 {
+        r: average_u8([values[0].r, values[1].r, values[2].r, values[3].r]),
+        g: average_u8([values[0].g, values[1].g, values[2].g, values[3].g]),
+        b: average_u8([values[0].b, values[1].b, values[2].b, values[3].b]),
+        a: average_u8([values[0].a, values[1].a, values[2].a, values[3].a]),
+    }
+}
+
+fn check_size<T>(data: &Bitmap<T>) -> Result<(), String> {
+    if data.width == 0 || data.height == 0 || data.buffer.len() != data.width * data.height {
+        Err(format!(
+            "Invalid image size {}x{} for {} pixels",
+            data.width,
+            data.height,
+            data.buffer.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// Number of bits of the fractional part of the stored heights
+const HEIGHT_FRACTION_BITS: i32 = 8;
+
+fn height_to_fixed(height: u16) -> FixedInt10 {
+    FixedInt10::from(height as i32) >> HEIGHT_FRACTION_BITS
+}
 
 impl HeightMap {
     pub fn get(&self, i: FixedInt10, j: FixedInt10) -> FixedInt10 {
-        let i = Into::<usize>::into(i.floor()) & 1023;
-        let j = Into::<usize>::into(j.floor()) & 1023;
-        self.0.buffer[i + self.width() * j].0.into()
+        self.get_lod(i, j, 0)
+    }
+
+    /// Same as get, but sample the given level of the mip chain
+    pub fn get_lod(&self, i: FixedInt10, j: FixedInt10, level: usize) -> FixedInt10 {
+        height_to_fixed(self.levels[level].sample(i, j))
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
     }
 
     pub fn get_interpolate(&self, i: FixedInt10, j: FixedInt10) -> FixedInt10 {
-        let i0 = Into::<usize>::into(i.floor()) & 1023;
-        let i1 = (i0 + 1) & 1023;
+        let i0: i32 = i.into();
         let i: FixedInt10 = i.fract();
         let ic = FixedInt10::from(1) - i;
 
-        let j0 = Into::<usize>::into(j.floor()) & 1023;
-        let j1 = (j0 + 1) & 1023;
+        let j0: i32 = j.into();
         let j: FixedInt10 = j.fract();
         let jc = FixedInt10::from(1) - j;
 
-        let f00: FixedInt10 = self.0.buffer[i0 + self.width() * j0].0.into();
-        let f10: FixedInt10 = self.0.buffer[i1 + self.width() * j0].0.into();
-        let f01: FixedInt10 = self.0.buffer[i0 + self.width() * j1].0.into();
-        let f11: FixedInt10 = self.0.buffer[i1 + self.width() * j1].0.into();
+        let base = &self.levels[0];
+        let f00 = height_to_fixed(base.at(i0, j0));
+        let f10 = height_to_fixed(base.at(i0 + 1, j0));
+        let f01 = height_to_fixed(base.at(i0, j0 + 1));
+        let f11 = height_to_fixed(base.at(i0 + 1, j0 + 1));
 
         // See https://en.wikipedia.org/wiki/Bilinear_interpolation#Unit_square
         f00 * ic * jc + f10 * i * jc + f01 * ic * j + f11 * i * j
     }
 
+    /// Load a grayscale image, 8 bits images are converted to 16 bits by lodepng
     pub fn from_file(path: &str) -> Result<HeightMap, String> {
-        match lodepng::decode_file(path, ColorType::GREY, 8) {
+        match lodepng::decode_file(path, ColorType::GREY, 16) {
             Err(e) => Err(format!("Error opening the file {} ({})", path, e)),
             Ok(image) => match image {
-                lodepng::Image::Grey(im) => {
-                    if (im.width, im.height) != (1024, 1024) {
-                        Err(format!(
-                            "Texture size must be exactly 1024x1024, is {}x{})",
-                            im.width, im.height
-                        ))
-                    } else {
-                        Ok(HeightMap::from(im))
-                    }
+                lodepng::Image::Grey16(im) => {
+                    check_size(&im)?;
+                    Ok(HeightMap::from_16bits(im))
                 }
                 _ => Err(format!(
-                    "Not the right format, expect grayscale 8 bits. Was {:?}",
+                    "Not the right format, expect grayscale 8 or 16 bits. Was {:?}",
                     image
                 )),
             },
         }
     }
 
-    pub fn from(data: Bitmap<Grey<u8>>) -> HeightMap {
-        HeightMap(data)
+    fn from_levels(width: usize, height: usize, data: Vec<u16>) -> HeightMap {
+        let base = Level::new(width, height, data, Border::Wrap);
+        HeightMap {
+            levels: mip_chain(base, average_u16),
+        }
     }
 
-    pub fn width(&self) -> usize {
-        1024
+    pub fn from(data: Bitmap<Grey<u8>>) -> HeightMap {
+        let heights = data
+            .buffer
+            .iter()
+            .map(|p| (p.0 as u16) << HEIGHT_FRACTION_BITS)
+            .collect();
+        HeightMap::from_levels(data.width, data.height, heights)
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use crate::fixed_int::FixedInt10;
-    use crate::terrain::HeightMap;
-    use lodepng::Bitmap;
-    use rgb::alt::Gray;
-
-    fn sample_map() -> HeightMap {
-        let mut data = vec![Gray(0u8); 1024 * 1024];
-        data[0 + 1024 * 0] = Gray(1u8);
-        data[1 + 1024 * 0] = Gray(2u8);
-        data[0 + 1024 * 1] = Gray(3u8);
-        data[1 + 1024 * 1] = Gray(4u8);
-
-        data[1023 + 1024 * 1023] = Gray(5u8);
 
-        HeightMap::from(Bitmap {
-            height: 2,
-            width: 2,
-            buffer: data,
-        })
+    /// The full 16 bits range covers the same heights as the 8 bits range: 0 to 255
+    pub fn from_16bits(data: Bitmap<Grey<u16>>) -> HeightMap {
+        // An 8 bits value v is stored as v * 257 in 16 bits, it maps back exactly to v
+        let heights = data
+            .buffer
+            .iter()
+            .map(|p| ((p.0 as u32 * 256 + 128) / 257) as u16)
+            .collect();
+        HeightMap::from_levels(data.width, data.height, heights)
     }
 
-    #[test]
-    fn get_boundaries() {
-        let map = sample_map();
-        assert_eq!(map.get_interpolate(0.into(), 0.into()), 1.into());
-        assert_eq!(map.get_interpolate(1.into(), 0.into()), 2.into());
-        assert_eq!(map.get_interpolate(0.into(), 1.into()), 3.into());
-        assert_eq!(map.get_interpolate(1.into(), 1.into()), 4.into());
+    /// Change what is returned outside of the map, the default is to wrap around
+    pub fn set_border(&mut self, border: Border<u8>) {
+        let border = match border {
+            Border::Wrap => Border::Wrap,
+            Border::Clamp => Border::Clamp,
+            Border::Constant(height) => Border::Constant((height as u16) << HEIGHT_FRACTION_BITS),
+        };
+        for level in self.levels.iter_mut() {
+            level.border = border;
+        }
+    }
 
-        assert_eq!(map.get_interpolate((1024 + 1).into(), 1.into()), 4.into());
-        assert_eq!(
-            map.get_interpolate((1024 + 1).into(), (1024 + 1).into()),
-            4.into()
-        );
-        assert_eq!(map.get_interpolate((-1).into(), (-1).into()), 5.into());
+    pub fn width(&self) -> usize {
+        self.levels[0].width
     }
 
-    #[test]
-    fn get_interpolation() {
-        let map = sample_map();
-        assert_eq!(
-            map.get_interpolate((0.5f32).into(), (0.5f32).into()),
-            FixedInt10::from(1 + 2 + 3 + 4) / 4
-        );
+    pub fn height(&self) -> usize {
+        self.levels[0].height
     }
 }
 
 impl Texture {
     pub fn get(&self, i: FixedInt10, j: FixedInt10) -> RGBA<u8> {
-        let i = Into::<usize>::into(i) & 1023;
-        let j = Into::<usize>::into(j) & 1023;
-        self.0.buffer[i + self.width() * j]
+        self.get_lod(i, j, 0)
+    }
+
+    /// Same as get, but sample the given level of the mip chain
+    pub fn get_lod(&self, i: FixedInt10, j: FixedInt10, level: usize) -> RGBA<u8> {
+        match &self.texels {
+            Texels::Rgba(levels) => levels[level].sample(i, j),
+            Texels::Palettized(p) => p.palette()[p.get_lod(i, j, level) as usize],
+        }
+    }
+
+    fn at(&self, i: i32, j: i32, level: usize) -> RGBA8 {
+        match &self.texels {
+            Texels::Rgba(levels) => levels[level].at(i, j),
+            Texels::Palettized(p) => p.palette()[p.levels()[level].at(i, j) as usize],
+        }
+    }
+
+    pub fn levels(&self) -> usize {
+        match &self.texels {
+            Texels::Rgba(levels) => levels.len(),
+            Texels::Palettized(p) => p.levels().len(),
+        }
     }
 
     fn u8_to_fixed_int_rgb(val: RGBA<u8>) -> RGBA<FixedInt10> {
@@ -132,20 +333,18 @@ impl Texture {
     }
 
     pub fn get_interpolate(&self, i: FixedInt10, j: FixedInt10) -> RGBA<u8> {
-        let i0 = Into::<usize>::into(i.floor()) & 1023;
-        let i1 = (i0 + 1) & 1023;
+        let i0: i32 = i.into();
         let i: FixedInt10 = i.fract();
         let ic = FixedInt10::from(1) - i;
 
-        let j0 = Into::<usize>::into(j.floor()) & 1023;
-        let j1 = (j0 + 1) & 1023;
+        let j0: i32 = j.into();
         let j: FixedInt10 = j.fract();
         let jc = FixedInt10::from(1) - j;
 
-        let f00 = Self::u8_to_fixed_int_rgb(self.0.buffer[i0 + self.width() * j0]);
-        let f10 = Self::u8_to_fixed_int_rgb(self.0.buffer[i1 + self.width() * j0]);
-        let f01 = Self::u8_to_fixed_int_rgb(self.0.buffer[i0 + self.width() * j1]);
-        let f11 = Self::u8_to_fixed_int_rgb(self.0.buffer[i1 + self.width() * j1]);
+        let f00 = Self::u8_to_fixed_int_rgb(self.at(i0, j0, 0));
+        let f10 = Self::u8_to_fixed_int_rgb(self.at(i0 + 1, j0, 0));
+        let f01 = Self::u8_to_fixed_int_rgb(self.at(i0, j0 + 1, 0));
+        let f11 = Self::u8_to_fixed_int_rgb(self.at(i0 + 1, j0 + 1, 0));
 
         // See https://en.wikipedia.org/wiki/Bilinear_interpolation#Unit_square
         RGBA {
@@ -161,14 +360,8 @@ impl Texture {
             Err(e) => Err(format!("Error opening the file {} ({})", path, e)),
             Ok(image) => match image {
                 lodepng::Image::RGBA(im) => {
-                    if (im.width, im.height) != (1024, 1024) {
-                        Err(format!(
-                            "Texture size must be exactly 1024x1024, is {}x{})",
-                            im.width, im.height
-                        ))
-                    } else {
-                        Ok(Texture::from(im))
-                    }
+                    check_size(&im)?;
+                    Ok(Texture::from(im))
                 }
                 _ => Err(format!(
                     "Not the right format, expect grayscale 8 bits. Was {:?}",
@@ -179,10 +372,212 @@ impl Texture {
     }
 
     pub fn from(data: Bitmap<RGBA<u8>>) -> Texture {
-        Texture(data)
+        let base = Level::new(data.width, data.height, data.buffer, Border::Wrap);
+        Texture {
+            texels: Texels::Rgba(mip_chain(base, average_rgba)),
+        }
+    }
+
+    /// Convert to 8 bits per texel, with a palette of up to 256 colors built from the texture
+    pub fn palettize(self) -> Texture {
+        match self.texels {
+            Texels::Rgba(levels) => Texture {
+                texels: Texels::Palettized(Palettized::new(&levels)),
+            },
+            Texels::Palettized(_) => self,
+        }
+    }
+
+    /// The indices and palette, when the texture is palettized
+    pub fn palettized(&self) -> Option<&Palettized> {
+        match &self.texels {
+            Texels::Rgba(_) => None,
+            Texels::Palettized(p) => Some(p),
+        }
+    }
+
+    /// Change what is returned outside of the texture, the default is to wrap around
+    pub fn set_border(&mut self, border: Border<RGBA8>) {
+        match &mut self.texels {
+            Texels::Rgba(levels) => {
+                for level in levels.iter_mut() {
+                    level.border = border;
+                }
+            }
+            Texels::Palettized(p) => p.set_border(border),
+        }
+    }
+
+    /// Memory taken by all the levels of the mip chain, and the tables of the palette
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.texels {
+            Texels::Rgba(levels) => levels.iter().map(|l| l.data.len() * 4).sum(),
+            Texels::Palettized(p) => p.size_in_bytes(),
+        }
     }
 
     pub fn width(&self) -> usize {
-        1024
+        self.size().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.size().1
+    }
+
+    fn size(&self) -> (usize, usize) {
+        match &self.texels {
+            Texels::Rgba(levels) => (levels[0].width, levels[0].height),
+            Texels::Palettized(p) => (p.levels()[0].width, p.levels()[0].height),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixed_int::FixedInt10;
+    use crate::terrain::{Border, HeightMap};
+    use lodepng::Bitmap;
+    use rgb::alt::Gray;
+
+    fn sample_map() -> HeightMap {
+        let mut data = vec![Gray(0u8); 1024 * 1024];
+        data[0] = Gray(1u8);
+        data[1] = Gray(2u8);
+        data[1024] = Gray(3u8);
+        data[1 + 1024] = Gray(4u8);
+
+        data[1023 + 1024 * 1023] = Gray(5u8);
+
+        HeightMap::from(Bitmap {
+            height: 1024,
+            width: 1024,
+            buffer: data,
+        })
+    }
+
+    #[test]
+    fn get_boundaries() {
+        let map = sample_map();
+        assert_eq!(map.get_interpolate(0.into(), 0.into()), 1.into());
+        assert_eq!(map.get_interpolate(1.into(), 0.into()), 2.into());
+        assert_eq!(map.get_interpolate(0.into(), 1.into()), 3.into());
+        assert_eq!(map.get_interpolate(1.into(), 1.into()), 4.into());
+
+        assert_eq!(map.get_interpolate((1024 + 1).into(), 1.into()), 4.into());
+        assert_eq!(
+            map.get_interpolate((1024 + 1).into(), (1024 + 1).into()),
+            4.into()
+        );
+        assert_eq!(map.get_interpolate((-1).into(), (-1).into()), 5.into());
+    }
+
+    #[test]
+    fn mip_levels() {
+        let map = sample_map();
+        assert_eq!(map.levels(), 11);
+        // (1 + 2 + 3 + 4) / 4, the fractional part is kept
+        assert_eq!(map.get_lod(0.into(), 0.into(), 1), 2.5f32.into());
+        assert_eq!(map.get_lod(1.into(), 1.into(), 1), 2.5f32.into());
+        assert_eq!(map.get_lod(2.into(), 0.into(), 1), 0.into());
+        // the single pixel of the last level wraps everywhere
+        assert_eq!(
+            map.get_lod(500.into(), (-20).into(), 10),
+            map.get_lod(0.into(), 0.into(), 10)
+        );
+    }
+
+    #[test]
+    fn any_size() {
+        let map = HeightMap::from(Bitmap {
+            width: 3,
+            height: 2,
+            buffer: (1..=6).map(Gray).collect(),
+        });
+        assert_eq!((map.width(), map.height()), (3, 2));
+        assert_eq!(map.get(2.into(), 1.into()), 6.into());
+        assert_eq!(map.get(3.into(), 0.into()), 1.into());
+        assert_eq!(map.get((-1).into(), 0.into()), 3.into());
+        assert_eq!(map.get(1.into(), (-1).into()), 5.into());
+        assert_eq!(map.get_interpolate(2.5f32.into(), 0.into()), 2.into());
+
+        // 3x2, 2x1 and 1x1
+        assert_eq!(map.levels(), 3);
+        // (1 + 2 + 4 + 5) / 4
+        assert_eq!(map.get_lod(0.into(), 0.into(), 1), 3.into());
+    }
+
+    #[test]
+    fn odd_sizes() {
+        for &size in &[5, 1000] {
+            let mut map = HeightMap::from(Bitmap {
+                width: size,
+                height: size,
+                buffer: (0..size * size).map(|p| Gray((p % 7 + 1) as u8)).collect(),
+            });
+            let end = FixedInt10::from(size as i32);
+            let last = end - FixedInt10::from_bits(1);
+            for level in 0..map.levels() {
+                // Every level wraps with the period of the base level
+                map.set_border(Border::Wrap);
+                for &i in &[0, 3, size as i32 / 2, size as i32 - 1] {
+                    let i = FixedInt10::from(i);
+                    assert_eq!(
+                        map.get_lod(i, i, level),
+                        map.get_lod(i + size as i32, i - size as i32, level),
+                        "{}x{} at level {}",
+                        size,
+                        size,
+                        level
+                    );
+                }
+
+                // and covers the same extent, down to its last row and column
+                map.set_border(Border::Constant(0));
+                assert_ne!(map.get_lod(last, last, level), 0.into());
+                assert_eq!(map.get_lod(end, 0.into(), level), 0.into());
+                assert_eq!(map.get_lod(0.into(), end, level), 0.into());
+            }
+        }
+    }
+
+    #[test]
+    fn borders() {
+        let mut map = sample_map();
+        map.set_border(Border::Clamp);
+        assert_eq!(map.get((-10).into(), (-3).into()), 1.into());
+        assert_eq!(map.get(1.into(), (-3).into()), 2.into());
+        assert_eq!(map.get(2000.into(), 2000.into()), 5.into());
+        assert_eq!(map.get_lod((-10).into(), 0.into(), 1), 2.5f32.into());
+
+        map.set_border(Border::Constant(7));
+        assert_eq!(map.get((-1).into(), 0.into()), 7.into());
+        assert_eq!(map.get(0.into(), 1024.into()), 7.into());
+        assert_eq!(map.get(1023.into(), 1023.into()), 5.into());
+        assert_eq!(map.get_lod((-1).into(), 0.into(), 3), 7.into());
+        assert_eq!(map.get_interpolate((-0.5f32).into(), 0.into()), 4.into());
+    }
+
+    #[test]
+    fn precision_16bits() {
+        let map = HeightMap::from_16bits(Bitmap {
+            width: 2,
+            height: 1,
+            buffer: vec![Gray(200 * 257), Gray(200 * 257 + 128)],
+        });
+        assert_eq!(map.get(0.into(), 0.into()), 200.into());
+        assert_eq!(map.get(1.into(), 0.into()), 200.5f32.into());
+        assert_eq!(
+            map.get_interpolate(0.5f32.into(), 0.into()),
+            200.25f32.into()
+        );
+    }
+
+    #[test]
+    fn get_interpolation() {
+        let map = sample_map();
+        assert_eq!(
+            map.get_interpolate((0.5f32).into(), (0.5f32).into()),
+            FixedInt10::from(1 + 2 + 3 + 4) / 4
+        );
     }
 }
@@ -0,0 +1,196 @@
+//! Build a heightmap and a matching texture from fractal value noise, so that the engine can run
+//! without any image on disk. The generated maps tile, like the images usually do.
+
+use crate::config::GeneratorConfig;
+use crate::terrain::{HeightMap, Texture};
+use lodepng::{Bitmap, Grey};
+use rgb::RGBA8;
+
+// Number of noise cells across the map for the first octave, it doubles for each octave
+const BASE_CELLS: u32 = 4;
+
+// Everything below this height is flattened and painted as water
+const WATER_LEVEL: f32 = 60.;
+const SAND_LEVEL: f32 = WATER_LEVEL + 6.;
+const GRASS_LEVEL: f32 = 150.;
+const SNOW_LEVEL: f32 = 200.;
+// Height difference between neighbouring pixels above which the ground is bare rock
+const ROCK_SLOPE: f32 = 5.;
+
+/// Pseudo random value in [0, 1) for a point of the noise lattice, based on splitmix64
+fn lattice_value(x: u32, y: u32, octave: u32, seed: u64) -> f32 {
+    let mut h = seed
+        ^ (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (octave as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+    h = (h ^ (h >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h ^= h >> 31;
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3. - 2. * t)
+}
+
+/// Smoothly interpolated noise, the lattice wraps after `cells` cells in both directions
+fn value_noise(x: f32, y: f32, cells: u32, octave: u32, seed: u64) -> f32 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let tx = smoothstep(x - x0);
+    let ty = smoothstep(y - y0);
+
+    let x0 = x0 as u32 % cells;
+    let y0 = y0 as u32 % cells;
+    let x1 = (x0 + 1) % cells;
+    let y1 = (y0 + 1) % cells;
+
+    let top =
+        lattice_value(x0, y0, octave, seed) * (1. - tx) + lattice_value(x1, y0, octave, seed) * tx;
+    let bottom =
+        lattice_value(x0, y1, octave, seed) * (1. - tx) + lattice_value(x1, y1, octave, seed) * tx;
+    top * (1. - ty) + bottom * ty
+}
+
+/// Sum the octaves of noise, each one with twice the frequency of the previous one and its
+/// amplitude multiplied by the roughness
+fn fractal_noise(i: usize, j: usize, config: &GeneratorConfig) -> f32 {
+    let mut value = 0.;
+    let mut amplitude = 1.;
+    for octave in 0..config.octaves {
+        let cells = BASE_CELLS << octave;
+        let scale = cells as f32 / config.size as f32;
+        value += amplitude
+            * value_noise(
+                i as f32 * scale,
+                j as f32 * scale,
+                cells,
+                octave,
+                config.seed,
+            );
+        amplitude *= config.roughness;
+    }
+    value
+}
+
+/// Generate a square heightmap using the full range of heights, with flat seas. The size must be
+/// at least 1, loading the config checks it.
+pub fn heightmap(config: &GeneratorConfig) -> HeightMap {
+    let size = config.size;
+    let noise: Vec<f32> = (0..size * size)
+        .map(|p| fractal_noise(p % size, p / size, config))
+        .collect();
+
+    let min = noise.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = noise.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let range = (max - min).max(f32::EPSILON);
+
+    let buffer = noise
+        .iter()
+        .map(|&v| {
+            let height = ((v - min) / range * 255.).max(WATER_LEVEL);
+            Grey((height * 257.) as u16)
+        })
+        .collect();
+
+    HeightMap::from_16bits(Bitmap {
+        buffer,
+        width: size,
+        height: size,
+    })
+}
+
+fn shade(color: (u8, u8, u8), brightness: f32) -> RGBA8 {
+    let apply = |c: u8| (c as f32 * brightness).min(255.) as u8;
+    RGBA8::new(apply(color.0), apply(color.1), apply(color.2), 255)
+}
+
+/// Color the map by height bands: water, sand, grass, rock and snow. Steep slopes are rock.
+pub fn texture(map: &HeightMap) -> Texture {
+    let (width, height) = (map.width(), map.height());
+    let height_at =
+        |i: usize, j: usize| -> f32 { map.get((i as i32).into(), (j as i32).into()).into() };
+
+    let mut buffer = Vec::with_capacity(width * height);
+    for j in 0..height {
+        for i in 0..width {
+            let h = height_at(i, j);
+            let slope_x = height_at(i + 1, j) - height_at(i + width - 1, j);
+            let slope_y = height_at(i, j + 1) - height_at(i, j + height - 1);
+            let slope = slope_x.abs().max(slope_y.abs()) / 2.;
+            let brightness = 0.8 + 0.4 * h / 255.;
+
+            let color = if h <= WATER_LEVEL {
+                (30, 70, 160)
+            } else if h < SAND_LEVEL {
+                (194, 178, 128)
+            } else if slope > ROCK_SLOPE {
+                (110, 100, 90)
+            } else if h < GRASS_LEVEL {
+                (60, 130, 50)
+            } else if h < SNOW_LEVEL {
+                (120, 110, 100)
+            } else {
+                (235, 235, 240)
+            };
+            buffer.push(shade(color, brightness));
+        }
+    }
+
+    Texture::from(Bitmap {
+        buffer,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::GeneratorConfig;
+    use crate::terrain::generate;
+
+    fn config(seed: u64) -> GeneratorConfig {
+        GeneratorConfig {
+            seed,
+            octaves: 4,
+            roughness: 0.5,
+            size: 64,
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let a = generate::heightmap(&config(1));
+        let b = generate::heightmap(&config(1));
+        let c = generate::heightmap(&config(2));
+        assert_eq!((a.width(), a.height()), (64, 64));
+
+        let mut differ = false;
+        for j in 0..64 {
+            for i in 0..64 {
+                assert_eq!(a.get(i.into(), j.into()), b.get(i.into(), j.into()));
+                differ |= a.get(i.into(), j.into()) != c.get(i.into(), j.into());
+            }
+        }
+        assert!(differ);
+    }
+
+    #[test]
+    fn heights_and_colors() {
+        let map = generate::heightmap(&config(3));
+        let texture = generate::texture(&map);
+
+        let heights: Vec<f32> = (0..64 * 64)
+            .map(|p| map.get((p % 64).into(), (p / 64).into()).into())
+            .collect();
+        let max = heights.iter().cloned().fold(0., f32::max);
+        let min = heights.iter().cloned().fold(255., f32::min);
+        assert!(max > 254.);
+        assert_eq!(min, 60.);
+
+        // The lowest points are all under water
+        let lowest = heights.iter().position(|&h| h == min).unwrap() as i32;
+        let water = texture.get((lowest % 64).into(), (lowest / 64).into());
+        assert!(water.b > water.r && water.b > water.g);
+    }
+}
@@ -0,0 +1,382 @@
+//! Lambert shading of the terrain from the normals of the heightmap, and shadows cast by the
+//! terrain itself. The light is computed once per texel and cached in a lightmap, which only
+//! needs to be rebuilt when the sun moves.
+
+use crate::config::RendererConfig;
+use crate::fixed_int::FixedInt10;
+use crate::terrain::{average_u8, clamp, mip_chain, wrap, Border, HeightMap, Level};
+use rgb::RGBA8;
+
+/// Position of the sun and what it does to the terrain, as set in the renderer config
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sun {
+    // Angles in degrees, the elevation is 0 on the horizon and 90 at the zenith
+    pub azimuth: f32,
+    pub elevation: f32,
+    // Light received by the faces that do not see the sun, between 0 and 1
+    pub ambient: f32,
+    // Darken the faces turned away from the sun
+    pub shading: bool,
+    // Height below the shadow line over which the shadow goes from light to dark, when the
+    // terrain casts shadows
+    pub shadow_softness: Option<f32>,
+}
+
+impl Sun {
+    pub fn from_config(config: &RendererConfig) -> Sun {
+        Sun {
+            azimuth: config.sun_azimuth,
+            elevation: config.sun_elevation,
+            ambient: config.ambient,
+            shading: config.enable_lighting,
+            shadow_softness: if config.enable_shadows {
+                Some(config.shadow_softness)
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Unit vector pointing toward the sun
+    pub fn direction(&self) -> (f32, f32, f32) {
+        let azimuth = self.azimuth.to_radians();
+        let elevation = self.elevation.to_radians();
+        (
+            elevation.cos() * azimuth.cos(),
+            elevation.cos() * azimuth.sin(),
+            elevation.sin(),
+        )
+    }
+
+    /// Light received by a face with the given unit normal, 255 is full light. The visibility of
+    /// the sun goes from 0 in the shadows to 1.
+    fn intensity(&self, normal: (f32, f32, f32), visibility: f32) -> u8 {
+        let lambert = if self.shading {
+            let sun = self.direction();
+            (normal.0 * sun.0 + normal.1 * sun.1 + normal.2 * sun.2).max(0.)
+        } else {
+            1.
+        };
+        ((self.ambient + (1. - self.ambient) * lambert * visibility).min(1.) * 255.).round() as u8
+    }
+
+    fn visibility(&self, height: f32, shadow_height: f32) -> f32 {
+        match self.shadow_softness {
+            None => 1.,
+            Some(softness) if softness > 0. => {
+                (1. - (shadow_height - height) / softness).clamp(0., 1.)
+            }
+            Some(_) if shadow_height > height => 0.,
+            Some(_) => 1.,
+        }
+    }
+}
+
+/// Height of the line above which the sun is visible, for every texel of the map. A texel is in
+/// the shadow when this line is above the ground.
+///
+/// Instead of marching a ray toward the sun from every texel, we sweep the map away from the sun
+/// along its main axis, so that the texel one step closer to the sun is always computed first:
+/// its shadow line, minus the drop of the ray over that step, is carried over to the current
+/// texel. The cost is the same as a single pass on the map, cheap enough to redo whenever the
+/// sun moves.
+fn shadow_heights(map: &HeightMap, sun: &Sun, heights: &[f32]) -> Vec<f32> {
+    let (width, height) = (map.width(), map.height());
+    let mut shadows = heights.to_vec();
+
+    let (dx, dy, dz) = sun.direction();
+    let horizontal = (dx * dx + dy * dy).sqrt();
+    if horizontal < 1e-3 {
+        // Sun at the zenith, no shadows
+        return shadows;
+    }
+
+    // Work in (u, v) coordinates, u being the axis closest to the direction of the sun
+    let x_major = dx.abs() >= dy.abs();
+    let (nu, nv, du, dv) = if x_major {
+        (width, height, dx, dy)
+    } else {
+        (height, width, dy, dx)
+    };
+    let index = |u: usize, v: usize| {
+        if x_major {
+            u + v * width
+        } else {
+            v + u * width
+        }
+    };
+
+    // One step of u toward the sun moves v by slope, and the ray goes down by drop
+    let step_u: i32 = if du > 0. { 1 } else { -1 };
+    let slope = dv / du.abs();
+    let drop = dz / horizontal * (1. + slope * slope).sqrt();
+
+    let border = map.levels[0].border;
+    // The first pass starts from the heights on the edge, a second one is needed for the shadows
+    // to cross the edge of a map that wraps around
+    let passes = if border == Border::Wrap { 2 } else { 1 };
+
+    for _ in 0..passes {
+        for step in 0..nu {
+            // Start from the side of the sun
+            let u = if step_u > 0 { nu - 1 - step } else { step };
+            let upwind = u as i32 + step_u;
+            if border != Border::Wrap && (upwind < 0 || upwind >= nu as i32) {
+                continue;
+            }
+            let upwind = wrap(upwind, nu);
+
+            for v in 0..nv {
+                let position = v as f32 + slope;
+                let v0 = position.floor();
+                let t = position - v0;
+                let (v0, v1) = if border == Border::Wrap {
+                    (wrap(v0 as i32, nv), wrap(v0 as i32 + 1, nv))
+                } else {
+                    (clamp(v0 as i32, nv), clamp(v0 as i32 + 1, nv))
+                };
+                let carried =
+                    shadows[index(upwind, v0)] * (1. - t) + shadows[index(upwind, v1)] * t - drop;
+
+                let current = index(u, v);
+                shadows[current] = heights[current].max(carried);
+            }
+        }
+    }
+    shadows
+}
+
+/// Darken the color by the light, 255 being full light
+pub fn shade(color: RGBA8, light: u8) -> RGBA8 {
+    let light = light as u16 + 1;
+    RGBA8 {
+        r: ((color.r as u16 * light) >> 8) as u8,
+        g: ((color.g as u16 * light) >> 8) as u8,
+        b: ((color.b as u16 * light) >> 8) as u8,
+        a: color.a,
+    }
+}
+
+pub struct Lightmap {
+    levels: Vec<Level<u8>>,
+    sun: Sun,
+}
+
+impl Lightmap {
+    /// Build the lightmap when the config needs one, for lighting, shadows or both
+    pub fn from_config(map: &HeightMap, config: &RendererConfig) -> Option<Lightmap> {
+        if config.enable_lighting || config.enable_shadows {
+            Some(Lightmap::new(map, Sun::from_config(config)))
+        } else {
+            None
+        }
+    }
+
+    pub fn new(map: &HeightMap, sun: Sun) -> Lightmap {
+        let (width, height) = (map.width(), map.height());
+        let height_at = |i: i32, j: i32| -> f32 { map.get(i.into(), j.into()).into() };
+
+        let heights: Vec<f32> = (0..width * height)
+            .map(|p| height_at((p % width) as i32, (p / width) as i32))
+            .collect();
+        let shadows = if sun.shadow_softness.is_some() {
+            shadow_heights(map, &sun, &heights)
+        } else {
+            heights.clone()
+        };
+
+        let mut data = Vec::with_capacity(width * height);
+        for j in 0..height as i32 {
+            for i in 0..width as i32 {
+                // Central differences, the neighbours follow the border of the map
+                let dx = (height_at(i + 1, j) - height_at(i - 1, j)) / 2.;
+                let dy = (height_at(i, j + 1) - height_at(i, j - 1)) / 2.;
+                let norm = (dx * dx + dy * dy + 1.).sqrt();
+                let p = i as usize + j as usize * width;
+                data.push(sun.intensity(
+                    (-dx / norm, -dy / norm, 1. / norm),
+                    sun.visibility(heights[p], shadows[p]),
+                ));
+            }
+        }
+
+        // Outside of the map the ground is flat and in the sun when it is not repeated
+        let border = match map.levels[0].border {
+            Border::Wrap => Border::Wrap,
+            Border::Clamp => Border::Clamp,
+            Border::Constant(_) => Border::Constant(sun.intensity((0., 0., 1.), 1.)),
+        };
+
+        let base = Level::new(width, height, data, border);
+        Lightmap {
+            levels: mip_chain(base, average_u8),
+            sun,
+        }
+    }
+
+    /// Rebuild the lightmap if the sun moved, return true if it did
+    pub fn update(&mut self, map: &HeightMap, sun: Sun) -> bool {
+        if sun == self.sun {
+            false
+        } else {
+            *self = Lightmap::new(map, sun);
+            true
+        }
+    }
+
+    /// Light at (i, j) in the given level of the mip chain, 255 is full light
+    pub fn get_lod(&self, i: FixedInt10, j: FixedInt10, level: usize) -> u8 {
+        self.levels[level].sample(i, j)
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terrain::lighting::{Lightmap, Sun};
+    use crate::terrain::{Border, HeightMap};
+    use lodepng::Bitmap;
+    use rgb::alt::Gray;
+
+    // A ramp going up toward +x
+    fn ramp() -> HeightMap {
+        HeightMap::from(Bitmap {
+            width: 8,
+            height: 8,
+            buffer: (0..64).map(|p| Gray((p % 8) as u8 * 2)).collect(),
+        })
+    }
+
+    fn lit() -> Sun {
+        Sun {
+            azimuth: 0.,
+            elevation: 45.,
+            ambient: 0.2,
+            shading: true,
+            shadow_softness: None,
+        }
+    }
+
+    // A wall of height 100 in the middle of flat ground
+    fn wall() -> HeightMap {
+        HeightMap::from(Bitmap {
+            width: 16,
+            height: 16,
+            buffer: (0..256)
+                .map(|p| Gray(if p % 16 == 8 { 100 } else { 0 }))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn lambert() {
+        let map = ramp();
+        let facing = Lightmap::new(
+            &map,
+            Sun {
+                azimuth: 180.,
+                ..lit()
+            },
+        );
+        let away = Lightmap::new(
+            &map,
+            Sun {
+                azimuth: 0.,
+                ..lit()
+            },
+        );
+        // The slope faces -x, so it is brighter with the sun on that side
+        assert!(facing.get_lod(3.into(), 3.into(), 0) > away.get_lod(3.into(), 3.into(), 0));
+        // The sun is low enough on the other side that only the ambient light remains
+        assert_eq!(away.get_lod(3.into(), 3.into(), 0), (0.2 * 255.) as u8);
+    }
+
+    #[test]
+    fn update_and_border() {
+        let mut map = ramp();
+        map.set_border(Border::Constant(0));
+        let sun = Sun {
+            elevation: 90.,
+            ambient: 0.,
+            ..lit()
+        };
+        let mut lightmap = Lightmap::new(&map, sun);
+        assert_eq!(lightmap.get_lod((-5).into(), 2.into(), 0), 255);
+        assert_eq!(lightmap.levels(), 4);
+
+        assert!(!lightmap.update(&map, sun));
+        assert!(lightmap.update(
+            &map,
+            Sun {
+                ambient: 0.5,
+                ..sun
+            }
+        ));
+    }
+
+    #[test]
+    fn shadows() {
+        let mut map = wall();
+        map.set_border(Border::Constant(0));
+        // The sun in the +x direction, 45 degrees up, so the wall casts a 100 texels shadow
+        let sun = Sun {
+            ambient: 0.,
+            shading: false,
+            shadow_softness: Some(0.),
+            ..lit()
+        };
+        let lightmap = Lightmap::new(&map, sun);
+        let light = |i: i32| lightmap.get_lod(i.into(), 3.into(), 0);
+
+        assert_eq!(light(8), 255);
+        assert_eq!(light(12), 255);
+        assert_eq!(light(7), 0);
+        assert_eq!(light(0), 0);
+
+        // With a soft shadow, the light comes back progressively
+        let soft = Lightmap::new(
+            &map,
+            Sun {
+                shadow_softness: Some(200.),
+                ..sun
+            },
+        );
+        let soft_light = |i: i32| soft.get_lod(i.into(), 3.into(), 0);
+        // The shadow is deeper next to the wall
+        assert!(soft_light(7) > 0);
+        assert!(soft_light(7) < soft_light(0));
+        assert!(soft_light(0) < 255);
+    }
+
+    #[test]
+    fn shadows_wrap() {
+        // The sun toward -y, the wall along the x axis casts its shadow over the edge of the map
+        let map = HeightMap::from(Bitmap {
+            width: 16,
+            height: 16,
+            buffer: (0..256)
+                .map(|p| Gray(if p / 16 == 10 { 10 } else { 0 }))
+                .collect(),
+        });
+        let sun = Sun {
+            azimuth: 270.,
+            ambient: 0.,
+            shading: false,
+            shadow_softness: Some(0.),
+            ..lit()
+        };
+        let lightmap = Lightmap::new(&map, sun);
+        let light = |j: i32| lightmap.get_lod(5.into(), j.into(), 0);
+
+        assert_eq!(light(9), 255);
+        assert_eq!(light(10), 255);
+        assert_eq!(light(11), 0);
+        assert_eq!(light(15), 0);
+        assert_eq!(light(0), 0);
+        assert_eq!(light(3), 0);
+        assert_eq!(light(5), 255);
+    }
+}
@@ -0,0 +1,321 @@
+//! Textures of 8 bits per texel, each one an index in a palette of up to 256 colors, which take a
+//! quarter of the memory of the RGBA ones. The lighting goes through a table of the nearest color
+//! of the palette to each of its colors darkened, like the colormaps of the classic Comanche.
+
+use crate::fixed_int::FixedInt10;
+use crate::terrain::lighting::shade;
+use crate::terrain::{Border, Level};
+use rgb::RGBA8;
+use std::ops::Range;
+
+/// Levels of light in the table of shades, the light of the lightmap is rounded down to them
+pub const LIGHT_LEVELS: usize = 32;
+const LIGHT_SHIFT: u32 = 3;
+/// Levels of fog in the colormap the renderer builds from the palette, from none to only the sky
+pub const FOG_LEVELS: usize = 32;
+
+// The texture is sorted in bins of 5 bits per channel, both to build the palette and to find the
+// nearest color of the palette
+const BINS: usize = 1 << 15;
+
+// The palette also keeps darker shades of the texture, so that the lighting finds them. The
+// texture itself weighs as much as all of them together.
+const HISTOGRAM_SHADES: [(u8, u64); 4] = [(255, 3), (191, 1), (127, 1), (63, 1)];
+
+fn bin(color: RGBA8) -> usize {
+    ((color.r as usize >> 3) << 10) | ((color.g as usize >> 3) << 5) | (color.b as usize >> 3)
+}
+
+// Coordinate of the bin along the red, green or blue axis
+fn bin_channel(bin: usize, channel: usize) -> usize {
+    (bin >> (10 - 5 * channel)) & 31
+}
+
+// The color in the middle of the bin
+fn bin_center(bin: usize) -> RGBA8 {
+    let channel = |c| (bin_channel(bin, c) << 3 | 4) as u8;
+    RGBA8::new(channel(0), channel(1), channel(2), 255)
+}
+
+// The light a level stands for, from none to full
+fn level_light(level: usize) -> u8 {
+    (level * 255 / (LIGHT_LEVELS - 1)) as u8
+}
+
+fn nearest(palette: &[RGBA8], color: RGBA8) -> u8 {
+    let distance = |c: &RGBA8| {
+        let (r, g, b) = (
+            c.r as i32 - color.r as i32,
+            c.g as i32 - color.g as i32,
+            c.b as i32 - color.b as i32,
+        );
+        r * r + g * g + b * b
+    };
+    (0..palette.len())
+        .min_by_key(|&i| distance(&palette[i]))
+        .unwrap_or(0) as u8
+}
+
+/// Colors counted in a bin of the histogram
+#[derive(Clone, Copy, Default)]
+struct Bin {
+    count: u64,
+    sum: [u64; 3],
+}
+
+/// Channel along which the bins spread the most, and by how much
+fn widest_channel(bins: &[(usize, Bin)]) -> (usize, usize) {
+    (0..3)
+        .map(|channel| {
+            let values = bins.iter().map(|(b, _)| bin_channel(*b, channel));
+            let extent = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, extent)
+        })
+        .max_by_key(|&(_, extent)| extent)
+        .unwrap()
+}
+
+/// Median cut: split the box of bins that spreads the most at the median of its colors, until
+/// there are 256 boxes or all of them hold a single bin. Each box gives the average of its colors.
+fn median_cut(histogram: &[Bin]) -> Vec<RGBA8> {
+    let mut bins: Vec<(usize, Bin)> = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, b)| b.count > 0)
+        .map(|(i, &b)| (i, b))
+        .collect();
+    let mut boxes: Vec<Range<usize>> = Vec::with_capacity(256);
+    boxes.push(0..bins.len());
+
+    while boxes.len() < 256 {
+        let (k, channel, extent) = boxes
+            .iter()
+            .enumerate()
+            .map(|(k, range)| {
+                let (channel, extent) = widest_channel(&bins[range.clone()]);
+                (k, channel, extent)
+            })
+            .max_by_key(|&(_, _, extent)| extent)
+            .unwrap();
+        if extent == 0 {
+            break;
+        }
+
+        let range = boxes[k].clone();
+        let content = &mut bins[range.clone()];
+        content.sort_unstable_by_key(|(b, _)| bin_channel(*b, channel));
+        let total: u64 = content.iter().map(|(_, b)| b.count).sum();
+        let mut seen = 0;
+        let median = content
+            .iter()
+            .position(|(_, b)| {
+                seen += b.count;
+                seen * 2 >= total
+            })
+            .unwrap_or(0);
+        // Both halves keep at least a bin
+        let split = range.start + (median + 1).min(content.len() - 1);
+        boxes[k] = range.start..split;
+        boxes.push(split..range.end);
+    }
+
+    boxes
+        .iter()
+        .map(|range| {
+            let (count, sum) =
+                bins[range.clone()]
+                    .iter()
+                    .fold((0, [0; 3]), |(count, sum), (_, b)| {
+                        (
+                            count + b.count,
+                            [sum[0] + b.sum[0], sum[1] + b.sum[1], sum[2] + b.sum[2]],
+                        )
+                    });
+            let channel = |c: usize| ((sum[c] + count / 2) / count.max(1)) as u8;
+            RGBA8::new(channel(0), channel(1), channel(2), 255)
+        })
+        .collect()
+}
+
+pub struct Palettized {
+    levels: Vec<Level<u8>>,
+    palette: Vec<RGBA8>,
+    // Nearest color of the palette to each of its colors at each level of light, 256 per level
+    shades: Vec<u8>,
+}
+
+impl Palettized {
+    /// Build the palette from the base level of the RGBA texture, and replace every texel of
+    /// every level with the nearest color of the palette
+    pub(super) fn new(levels: &[Level<RGBA8>]) -> Palettized {
+        let mut histogram = vec![Bin::default(); BINS];
+        for &color in &levels[0].data {
+            for &(light, weight) in &HISTOGRAM_SHADES {
+                let color = shade(color, light);
+                let bin = &mut histogram[bin(color)];
+                bin.count += weight;
+                bin.sum[0] += color.r as u64 * weight;
+                bin.sum[1] += color.g as u64 * weight;
+                bin.sum[2] += color.b as u64 * weight;
+            }
+        }
+        let palette = median_cut(&histogram);
+        // Nearest color of the palette to the middle of each bin
+        let inverse: Vec<u8> = (0..BINS)
+            .map(|b| nearest(&palette, bin_center(b)))
+            .collect();
+
+        let shades = (0..LIGHT_LEVELS)
+            .flat_map(|level| {
+                let palette = &palette;
+                (0..256).map(move |i| match palette.get(i) {
+                    Some(&color) => nearest(palette, shade(color, level_light(level))),
+                    None => 0,
+                })
+            })
+            .collect();
+
+        let mut palettized = Palettized {
+            levels: levels
+                .iter()
+                .map(|level| Level {
+                    width: level.width,
+                    height: level.height,
+                    data: level.data.iter().map(|&c| inverse[bin(c)]).collect(),
+                    border: Border::Wrap,
+                    base_size: level.base_size,
+                    scale: level.scale,
+                })
+                .collect(),
+            palette,
+            shades,
+        };
+        palettized.set_border(levels[0].border);
+        palettized
+    }
+
+    /// Index of the color at (i, j) in the given level of the mip chain
+    pub fn get_lod(&self, i: FixedInt10, j: FixedInt10, level: usize) -> u8 {
+        self.levels[level].sample(i, j)
+    }
+
+    /// Index of the color darkened by the light, 255 being full light
+    pub fn shade(&self, index: u8, light: u8) -> u8 {
+        self.shades[((light >> LIGHT_SHIFT) as usize) << 8 | index as usize]
+    }
+
+    pub fn palette(&self) -> &[RGBA8] {
+        &self.palette
+    }
+
+    pub(super) fn levels(&self) -> &[Level<u8>] {
+        &self.levels
+    }
+
+    /// The constant color of the border becomes the nearest one of the palette
+    pub(super) fn set_border(&mut self, border: Border<RGBA8>) {
+        let border = match border {
+            Border::Wrap => Border::Wrap,
+            Border::Clamp => Border::Clamp,
+            Border::Constant(color) => Border::Constant(nearest(&self.palette, color)),
+        };
+        for level in self.levels.iter_mut() {
+            level.border = border;
+        }
+    }
+
+    /// The texels, the palette and the shades, and the colormap of the fog that the renderer
+    /// builds from the palette
+    pub(super) fn size_in_bytes(&self) -> usize {
+        let texels: usize = self.levels.iter().map(|l| l.data.len()).sum();
+        let fog_colors = FOG_LEVELS * 256 * 4;
+        texels + self.palette.len() * 4 + self.shades.len() + fog_colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terrain::palette::{level_light, median_cut, Bin, FOG_LEVELS, LIGHT_LEVELS};
+    use crate::terrain::{Border, Texture};
+    use lodepng::Bitmap;
+    use rgb::RGBA8;
+
+    fn gradient(size: usize) -> Texture {
+        Texture::from(Bitmap {
+            width: size,
+            height: size,
+            buffer: (0..size * size)
+                .map(|p| RGBA8::new((p % size * 255 / size) as u8, 80, 200, 255))
+                .collect(),
+        })
+    }
+
+    #[test]
+    fn few_colors_are_kept() {
+        let mut histogram = vec![Bin::default(); 1 << 15];
+        for &(bin, color) in &[(0, [0, 0, 0]), (31 << 10, [255, 0, 0]), (31, [0, 0, 250])] {
+            histogram[bin] = Bin {
+                count: 2,
+                sum: [color[0] * 2, color[1] * 2, color[2] * 2],
+            };
+        }
+        let mut palette = median_cut(&histogram);
+        palette.sort_by_key(|c| (c.r, c.b));
+        assert_eq!(
+            palette,
+            [
+                RGBA8::new(0, 0, 0, 255),
+                RGBA8::new(0, 0, 250, 255),
+                RGBA8::new(255, 0, 0, 255)
+            ]
+        );
+    }
+
+    #[test]
+    fn palettize() {
+        let texture = gradient(64);
+        let rgba_size = texture.size_in_bytes();
+        let palettized = texture.palettize();
+        assert_eq!(palettized.levels(), 7);
+
+        let inner = palettized.palettized().unwrap();
+        assert!(inner.palette().len() <= 256);
+        // A quarter of the RGBA texels, plus the palette, the shades and the fog
+        let tables = inner.palette().len() * 4 + LIGHT_LEVELS * 256 + FOG_LEVELS * 256 * 4;
+        assert_eq!(palettized.size_in_bytes(), rgba_size / 4 + tables);
+
+        let texel = palettized.get(40.into(), 3.into());
+        assert!(texel.r.abs_diff((40 * 255 / 64) as u8) <= 4);
+        assert_eq!((texel.g, texel.b), (80, 200));
+        assert_eq!(
+            palettized.get_lod(0.into(), 0.into(), 6),
+            inner.palette()[inner.get_lod(0.into(), 0.into(), 6) as usize]
+        );
+    }
+
+    #[test]
+    fn shades() {
+        let texture = gradient(64).palettize();
+        let inner = texture.palettized().unwrap();
+        let index = inner.get_lod(40.into(), 3.into(), 0);
+
+        assert_eq!(inner.shade(index, 255), index);
+        assert_eq!(level_light(LIGHT_LEVELS - 1), 255);
+        let half = inner.palette()[inner.shade(index, 128) as usize];
+        let full = inner.palette()[index as usize];
+        assert!(
+            half.b.abs_diff(full.b / 2) <= 8,
+            "{:?} for {:?}",
+            half,
+            full
+        );
+    }
+
+    #[test]
+    fn border() {
+        let mut texture = gradient(8).palettize();
+        texture.set_border(Border::Constant(RGBA8::new(0, 80, 200, 255)));
+        let outside = texture.get((-1).into(), 0.into());
+        assert_eq!(outside, texture.get(0.into(), 0.into()));
+    }
+}
@@ -0,0 +1,767 @@
+//! Render a few camera poses over a synthetic map and compare them with the reference images
+//! stored in tests/golden, and in tests/golden/integer and tests/golden/palettized for the
+//! integer path and the palettized textures. Run with `UPDATE_GOLDEN=1` to regenerate the
+//! references after an intentional change of the output.
+
+use std::env;
+use std::f32::consts::TAU;
+use std::path::{Path, PathBuf};
+
+use lodepng::{Bitmap, ColorType, Grey};
+use rgb::{RGB8, RGBA8};
+
+use mountain::camera::Camera;
+use mountain::config::{Dithering, FogModel, HeightFogConfig, RendererConfig};
+use mountain::fixed_int::FixedInt10;
+use mountain::framebuffer::{Framebuffer, MemoryFramebuffer, PixelFormat};
+use mountain::renderer::Renderer;
+use mountain::sky::Sky;
+use mountain::terrain::lighting::Lightmap;
+use mountain::terrain::{Border, HeightMap, Texture};
+
+const MAP_SIZE: usize = 1024;
+const SCREEN_W: usize = 160;
+const SCREEN_H: usize = 120;
+
+// Maximum difference allowed on each channel of each pixel
+const TOLERANCE: u8 = 4;
+
+#[derive(Clone, Copy)]
+struct Case {
+    name: &'static str,
+    x: f32,
+    y: f32,
+    // height of the camera above the terrain
+    altitude: i32,
+    // in turns, the cases written in radians are divided by TAU
+    angle: f32,
+    horizon: i32,
+    // in turns, positive to the right
+    roll: f32,
+    fov: f32,
+    filtering: bool,
+    lod: bool,
+    // Surround the map with water instead of repeating it
+    island: bool,
+    lighting: bool,
+    shadows: bool,
+}
+
+// The overview, which the other cases change
+const BASE: Case = Case {
+    name: "overview",
+    x: 512.,
+    y: 512.,
+    altitude: 120,
+    angle: 0.,
+    horizon: 60,
+    roll: 0.,
+    fov: 90.,
+    filtering: true,
+    lod: false,
+    island: false,
+    lighting: false,
+    shadows: false,
+};
+
+const CASES: &[Case] = &[
+    BASE,
+    Case {
+        name: "low_filtered",
+        x: 100.,
+        y: 700.,
+        altitude: 10,
+        angle: 1. / TAU,
+        horizon: 40,
+        ..BASE
+    },
+    Case {
+        name: "low_unfiltered",
+        x: 100.,
+        y: 700.,
+        altitude: 10,
+        angle: 1. / TAU,
+        horizon: 40,
+        filtering: false,
+        ..BASE
+    },
+    Case {
+        name: "map_border",
+        x: 1020.,
+        y: 4.,
+        altitude: 80,
+        angle: 2.5 / TAU,
+        horizon: 50,
+        ..BASE
+    },
+    Case {
+        name: "lod",
+        lod: true,
+        ..BASE
+    },
+    Case {
+        name: "island",
+        y: 1250.,
+        altitude: 80,
+        island: true,
+        ..BASE
+    },
+    Case {
+        name: "lighting",
+        x: 100.,
+        y: 700.,
+        altitude: 10,
+        angle: 1. / TAU,
+        horizon: 40,
+        lighting: true,
+        ..BASE
+    },
+    Case {
+        name: "shadows",
+        x: 100.,
+        y: 700.,
+        altitude: 10,
+        angle: 1. / TAU,
+        horizon: 40,
+        shadows: true,
+        ..BASE
+    },
+    Case {
+        name: "roll",
+        roll: 0.4 / TAU,
+        ..BASE
+    },
+    Case {
+        name: "zoom",
+        x: 100.,
+        y: 700.,
+        altitude: 10,
+        angle: 1. / TAU,
+        fov: 30.,
+        ..BASE
+    },
+];
+
+// Integer only, so that the map is identical on every platform
+fn triangle(v: usize, period: usize) -> usize {
+    let half = period / 2;
+    let p = v % period;
+    if p > half {
+        period - p
+    } else {
+        p
+    }
+}
+
+fn synthetic_height(x: usize, y: usize) -> u8 {
+    let sum = triangle(x, 256) + triangle(y, 320) * 3 / 4 + triangle(x + 2 * y, 128);
+    (sum * 255 / 312) as u8
+}
+
+fn synthetic_color(x: usize, y: usize) -> RGBA8 {
+    let height = synthetic_height(x, y);
+    let checker = ((x / 16 + y / 16) & 1) as u8 * 24;
+    match height {
+        0..=59 => RGBA8::new(20, 60, 160 + checker, 255),
+        60..=119 => RGBA8::new(40 + checker, 140, 50, 255),
+        120..=199 => RGBA8::new(120, 90 + checker, 60, 255),
+        _ => RGBA8::new(230, 230, 230 - checker, 255),
+    }
+}
+
+fn synthetic_terrain() -> (HeightMap, Texture) {
+    let mut heights = Vec::with_capacity(MAP_SIZE * MAP_SIZE);
+    let mut colors = Vec::with_capacity(MAP_SIZE * MAP_SIZE);
+    for y in 0..MAP_SIZE {
+        for x in 0..MAP_SIZE {
+            heights.push(Grey(synthetic_height(x, y)));
+            colors.push(synthetic_color(x, y));
+        }
+    }
+
+    let map = HeightMap::from(Bitmap {
+        buffer: heights,
+        width: MAP_SIZE,
+        height: MAP_SIZE,
+    });
+    let texture = Texture::from(Bitmap {
+        buffer: colors,
+        width: MAP_SIZE,
+        height: MAP_SIZE,
+    });
+    (map, texture)
+}
+
+// How the frame is rendered, the output should be the same either way
+#[derive(Clone, Copy)]
+struct Method {
+    threads: usize,
+    integer_only: bool,
+    format: PixelFormat,
+    dithering: Dithering,
+    // Changes the output, unlike the rest of the method
+    fog_model: FogModel,
+    fog_density: f32,
+    height_fog: Option<HeightFogConfig>,
+}
+
+const DEFAULT_METHOD: Method = Method {
+    threads: 1,
+    integer_only: false,
+    format: PixelFormat::RgbaBytes,
+    dithering: Dithering::None,
+    fog_model: FogModel::Linear,
+    fog_density: 0.,
+    height_fog: None,
+};
+
+fn render(case: &Case, map: &HeightMap, texture: &Texture, method: Method) -> Vec<RGB8> {
+    render_with_steps(case, map, texture, method).0
+}
+
+/// Render the case, and also return the number of steps taken in z
+fn render_with_steps(
+    case: &Case,
+    map: &HeightMap,
+    texture: &Texture,
+    method: Method,
+) -> (Vec<RGB8>, usize) {
+    render_with_sky(case, map, texture, &Sky::default(), method)
+}
+
+fn render_with_sky(
+    case: &Case,
+    map: &HeightMap,
+    texture: &Texture,
+    sky: &Sky,
+    method: Method,
+) -> (Vec<RGB8>, usize) {
+    let config = RendererConfig {
+        fog_start: 200,
+        distance_max: 300,
+        fog_model: method.fog_model,
+        fog_density: method.fog_density,
+        height_fog: method.height_fog,
+        enable_filtering: case.filtering,
+        fov: case.fov,
+        scale_height: 1.,
+        enable_lod: case.lod,
+        lod_distance: 60,
+        enable_lighting: case.lighting,
+        sun_elevation: 30.,
+        ambient: 0.3,
+        enable_shadows: case.shadows,
+        shadow_softness: 10.,
+        threads: method.threads,
+        integer_only: method.integer_only,
+        dithering: method.dithering,
+        ..RendererConfig::default()
+    };
+
+    let mut camera = Camera::new(case.x.into(), case.y.into(), 0.into(), case.horizon);
+    camera.update_angle(case.angle.into());
+    camera.update_roll(case.roll.into());
+    camera.z = FixedInt10::from(case.altitude) + map.get_interpolate(case.x.into(), case.y.into());
+
+    let lightmap = Lightmap::from_config(map, &config);
+
+    let mut screen = MemoryFramebuffer::new(SCREEN_W, SCREEN_H, method.format);
+    let z_steps = Renderer::new(sky.clone()).draw(
+        &mut screen,
+        map,
+        texture,
+        lightmap.as_ref(),
+        &camera,
+        &config,
+    );
+    let bytes_per_pixel = method.format.bytes_per_pixel();
+    assert_eq!(screen.pitch(), SCREEN_W * bytes_per_pixel);
+
+    let pixels = screen
+        .pixels()
+        .chunks(bytes_per_pixel)
+        .map(|p| decode(method.format, p))
+        .collect();
+    (pixels, z_steps)
+}
+
+/// Color of a pixel, the bits the 16 bits formats do not keep are 0
+fn decode(format: PixelFormat, p: &[u8]) -> RGB8 {
+    let packed = u16::from_ne_bytes([p[0], p[1]]);
+    match format {
+        PixelFormat::Rgb565 => RGB8::new(
+            (packed >> 11 << 3) as u8,
+            (packed >> 5 << 2) as u8,
+            (packed << 3) as u8,
+        ),
+        PixelFormat::Rgb555 => RGB8::new(
+            (packed >> 10 << 3) as u8,
+            (packed >> 5 << 3) as u8,
+            (packed << 3) as u8,
+        ),
+        PixelFormat::XrgbBytes => RGB8::new(p[1], p[2], p[3]),
+        PixelFormat::BgrxBytes => RGB8::new(p[2], p[1], p[0]),
+        PixelFormat::RgbaBytes => RGB8::new(p[0], p[1], p[2]),
+    }
+}
+
+// The references of a set of renders are in tests/golden/<set>, the float path is the empty set
+fn golden_path(set: &str, name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(set)
+        .join(format!("{}.png", name))
+}
+
+fn load_reference(path: &Path) -> Result<Vec<RGB8>, String> {
+    match lodepng::decode_file(path, ColorType::RGB, 8) {
+        Err(e) => Err(format!("Cannot open {}: {}", path.display(), e)),
+        Ok(lodepng::Image::RGB(im)) => {
+            if (im.width, im.height) != (SCREEN_W, SCREEN_H) {
+                Err(format!(
+                    "{} is {}x{}, expected {}x{}",
+                    path.display(),
+                    im.width,
+                    im.height,
+                    SCREEN_W,
+                    SCREEN_H
+                ))
+            } else {
+                Ok(im.buffer)
+            }
+        }
+        Ok(image) => Err(format!(
+            "Unexpected format for {}: {:?}",
+            path.display(),
+            image
+        )),
+    }
+}
+
+fn is_close(a: RGB8, b: RGB8) -> bool {
+    a.r.abs_diff(b.r) <= TOLERANCE
+        && a.g.abs_diff(b.g) <= TOLERANCE
+        && a.b.abs_diff(b.b) <= TOLERANCE
+}
+
+/// Write the rendered frame and a diff image (mismatches in red over a dimmed reference) next
+/// to the build artifacts, and return the number of mismatching pixels
+fn compare(name: &str, actual: &[RGB8], reference: &[RGB8]) -> usize {
+    let mismatches = actual
+        .iter()
+        .zip(reference)
+        .filter(|(&a, &r)| !is_close(a, r))
+        .count();
+
+    if mismatches > 0 {
+        let diff: Vec<RGB8> = actual
+            .iter()
+            .zip(reference)
+            .map(|(&a, &r)| {
+                if is_close(a, r) {
+                    let grey = ((r.r as u16 + r.g as u16 + r.b as u16) / 6) as u8;
+                    RGB8::new(grey, grey, grey)
+                } else {
+                    RGB8::new(255, 0, 0)
+                }
+            })
+            .collect();
+
+        let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let actual_path = output_dir.join(format!("{}-actual.png", name));
+        let diff_path = output_dir.join(format!("{}-diff.png", name));
+        lodepng::encode24_file(&actual_path, actual, SCREEN_W, SCREEN_H).unwrap();
+        lodepng::encode24_file(&diff_path, &diff, SCREEN_W, SCREEN_H).unwrap();
+        println!(
+            "{}: {} pixels differ, see {}",
+            name,
+            mismatches,
+            diff_path.display()
+        );
+    }
+    mismatches
+}
+
+/// Render every case with the method and compare it with its reference of the set. Run with
+/// UPDATE_GOLDEN=1 to write the references instead.
+fn check_goldens(set: &str, mut map: HeightMap, mut texture: Texture, method: Method) {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = Vec::new();
+    for case in CASES {
+        if case.island {
+            map.set_border(Border::Constant(0));
+            texture.set_border(Border::Constant(RGBA8::new(20, 60, 160, 255)));
+        } else {
+            map.set_border(Border::Wrap);
+            texture.set_border(Border::Wrap);
+        }
+
+        let actual = render(case, &map, &texture, method);
+        let path = golden_path(set, case.name);
+
+        if update {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            lodepng::encode24_file(&path, &actual, SCREEN_W, SCREEN_H).unwrap();
+            continue;
+        }
+
+        let name = match set {
+            "" => case.name.to_string(),
+            set => format!("{}_{}", case.name, set),
+        };
+        match load_reference(&path) {
+            Err(e) => failures.push(e),
+            Ok(reference) => {
+                if compare(&name, &actual, &reference) > 0 {
+                    failures.push(format!("{} does not match its reference", name));
+                }
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn golden_images() {
+    let (map, texture) = synthetic_terrain();
+    check_goldens("", map, texture, DEFAULT_METHOD);
+}
+
+#[test]
+fn parallel_rendering() {
+    let (map, texture) = synthetic_terrain();
+    // The strips do not all have the same width, and one of them crosses the middle of the screen
+    for case in CASES
+        .iter()
+        .filter(|c| ["overview", "roll"].contains(&c.name))
+    {
+        let single = render(case, &map, &texture, DEFAULT_METHOD);
+        let parallel = Method {
+            threads: 3,
+            ..DEFAULT_METHOD
+        };
+        assert!(
+            single == render(case, &map, &texture, parallel),
+            "{} differs when rendered on 3 threads",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn early_out() {
+    let (map, texture) = synthetic_terrain();
+    let overview = &CASES[0];
+
+    // Some of the sky is visible, every step is taken up to distance_max
+    let (_, z_steps) = render_with_steps(overview, &map, &texture, DEFAULT_METHOD);
+    assert_eq!(z_steps, 299);
+
+    // Looking down at the ground, which covers the whole screen close to the camera
+    let looking_down = Case {
+        horizon: 400,
+        ..*overview
+    };
+    let (pixels, z_steps) = render_with_steps(&looking_down, &map, &texture, DEFAULT_METHOD);
+    assert!(z_steps < 100, "{} steps taken", z_steps);
+    assert!(!pixels.contains(&RGB8::new(80, 120, 250)));
+
+    let (_, z_steps) = render_with_steps(
+        &looking_down,
+        &map,
+        &texture,
+        Method {
+            threads: 4,
+            ..DEFAULT_METHOD
+        },
+    );
+    assert!(z_steps < 4 * 100, "{} steps taken on 4 threads", z_steps);
+}
+
+#[test]
+fn integer_only() {
+    // The integer path steps through the view with more precision, so the texels sampled near
+    // their edges are not always the ones of the float path: it has its own references
+    let (map, texture) = synthetic_terrain();
+    let method = Method {
+        integer_only: true,
+        ..DEFAULT_METHOD
+    };
+    check_goldens("integer", map, texture, method);
+}
+
+#[test]
+fn pixel_formats() {
+    let (map, texture) = synthetic_terrain();
+    let overview = &CASES[0];
+    let reference = render(overview, &map, &texture, DEFAULT_METHOD);
+
+    // Every format gets the same colors, down to the bits it keeps of each channel
+    let formats = [
+        (PixelFormat::Rgb565, RGB8::new(0xf8, 0xfc, 0xf8)),
+        (PixelFormat::Rgb555, RGB8::new(0xf8, 0xf8, 0xf8)),
+        (PixelFormat::XrgbBytes, RGB8::new(0xff, 0xff, 0xff)),
+        (PixelFormat::BgrxBytes, RGB8::new(0xff, 0xff, 0xff)),
+    ];
+    for &(format, mask) in &formats {
+        let expected: Vec<RGB8> = reference
+            .iter()
+            .map(|p| RGB8::new(p.r & mask.r, p.g & mask.g, p.b & mask.b))
+            .collect();
+        for &threads in &[1, 3] {
+            let method = Method {
+                threads,
+                format,
+                ..DEFAULT_METHOD
+            };
+            assert!(
+                expected == render(overview, &map, &texture, method),
+                "{:?} differs on {} threads",
+                format,
+                threads
+            );
+        }
+    }
+}
+
+#[test]
+fn dithering() {
+    let (map, texture) = synthetic_terrain();
+    let overview = &CASES[0];
+    let reference = render(overview, &map, &texture, DEFAULT_METHOD);
+    let mean = |pixels: &[RGB8]| {
+        pixels
+            .iter()
+            .map(|p| p.r as f32 + p.g as f32 + p.b as f32)
+            .sum::<f32>()
+            / pixels.len() as f32
+    };
+
+    let truncated = Method {
+        format: PixelFormat::Rgb565,
+        ..DEFAULT_METHOD
+    };
+    let truncated = render(overview, &map, &texture, truncated);
+
+    for &dithering in &[Dithering::Bayer, Dithering::BlueNoise] {
+        let method = Method {
+            format: PixelFormat::Rgb565,
+            dithering,
+            ..DEFAULT_METHOD
+        };
+        let dithered = render(overview, &map, &texture, method);
+
+        // Each channel is rounded either down or up to the next step of the format
+        for (d, t) in dithered.iter().zip(&truncated) {
+            for &(d, t, step) in &[(d.r, t.r, 8), (d.g, t.g, 4), (d.b, t.b, 8)] {
+                assert!(
+                    d == t || d as u16 == t as u16 + step,
+                    "{:?}: {} from {}",
+                    dithering,
+                    d,
+                    t
+                );
+            }
+        }
+        // Rounding down darkens the image, the dithering keeps most of its brightness: only the
+        // channels already at the top step, like the blue of the sky, cannot be rounded up
+        assert!(
+            (mean(&dithered) - mean(&reference)).abs()
+                < (mean(&truncated) - mean(&reference)).abs() / 2.,
+            "{:?} changes the brightness",
+            dithering
+        );
+
+        // The pattern follows the screen, not the strips
+        let parallel = Method {
+            threads: 3,
+            ..method
+        };
+        assert!(
+            dithered == render(overview, &map, &texture, parallel),
+            "{:?} differs on 3 threads",
+            dithering
+        );
+    }
+}
+
+#[test]
+fn palettized() {
+    let (map, texture) = synthetic_terrain();
+    let rgba_size = texture.size_in_bytes();
+    let texture = texture.palettize();
+    assert!(texture.size_in_bytes() < rgba_size / 3);
+
+    // The lighting snaps to the colors of the palette and the texture is not filtered, so most
+    // pixels are a little off the RGBA references, but none of the images drifts
+    for case in CASES.iter().filter(|c| !c.island) {
+        let actual = render(case, &map, &texture, DEFAULT_METHOD);
+        let reference = load_reference(&golden_path("", case.name)).unwrap();
+        let error = actual
+            .iter()
+            .zip(&reference)
+            .map(|(a, r)| {
+                a.r.abs_diff(r.r) as u32 + a.g.abs_diff(r.g) as u32 + a.b.abs_diff(r.b) as u32
+            })
+            .sum::<u32>() as f32
+            / (3 * actual.len()) as f32;
+        assert!(
+            error < 4.,
+            "{}: the channels are off by {} on average with the palette",
+            case.name,
+            error
+        );
+    }
+
+    // Within the tolerance of the other references, the palette and the colormaps have their own
+    check_goldens("palettized", map, texture, DEFAULT_METHOD);
+}
+
+#[test]
+fn sky() {
+    let (map, texture) = synthetic_terrain();
+    let overview = &CASES[0];
+    let colors = [RGBA8::new(200, 200, 240, 0), RGBA8::new(20, 40, 160, 0)];
+    let (solid, _) = render_with_sky(
+        overview,
+        &map,
+        &texture,
+        &Sky::color(colors[0]),
+        DEFAULT_METHOD,
+    );
+    let (gradient, _) = render_with_sky(
+        overview,
+        &map,
+        &texture,
+        &Sky::gradient(&colors).unwrap(),
+        DEFAULT_METHOD,
+    );
+
+    // The fog fades into the first color of the gradient, so nothing changes up to the horizon
+    let horizon_row = SCREEN_H - 1 - overview.horizon as usize;
+    assert!(solid[horizon_row * SCREEN_W..] == gradient[horizon_row * SCREEN_W..]);
+    // The top row is 59 rows above the horizon, out of the 120 of the gradient
+    let t = 59. / 120.;
+    let expected = |from: u8, to: u8| from as f32 + (to as f32 - from as f32) * t;
+    for p in &gradient[..SCREEN_W] {
+        assert!(
+            (p.r as f32 - expected(colors[0].r, colors[1].r)).abs() < 2.
+                && (p.b as f32 - expected(colors[0].b, colors[1].b)).abs() < 2.,
+            "{:?} at the top of the gradient",
+            p
+        );
+    }
+
+    // Each column of the panorama has its own color, the top row is blue and the bottom one is
+    // not. At 90 degrees, the 640 columns of a turn are one per pixel.
+    let width = 640;
+    let buffer = (0..2 * width)
+        .map(|p| {
+            RGBA8::new(
+                (p % width % 256) as u8,
+                (p % width / 256) as u8,
+                255 * (p < width) as u8,
+                255,
+            )
+        })
+        .collect();
+    let sky = Sky::panorama(Bitmap {
+        width,
+        height: 2,
+        buffer,
+    })
+    .unwrap();
+    assert_eq!(sky.fog_color(), RGBA8::new(114, 0, 0, 0));
+
+    for &angle in &[0., 0.25] {
+        let case = Case { angle, ..*overview };
+        let (pixels, _) = render_with_sky(&case, &map, &texture, &sky, DEFAULT_METHOD);
+        // Turning left moves the panorama to the right
+        let first = -80 - (angle * width as f32) as i32;
+        for (i, p) in pixels[..SCREEN_W].iter().enumerate() {
+            let u = (first + i as i32).rem_euclid(width as i32);
+            assert_eq!(
+                (p.r as i32 + 256 * p.g as i32, p.b),
+                (u, 255),
+                "column {} at {}",
+                i,
+                angle
+            );
+        }
+    }
+
+    // Looking up from above the mountains, the bottom row of the panorama is on the top row of
+    // the screen
+    let looking_up = Case {
+        altitude: 400,
+        horizon: SCREEN_H as i32 - 1,
+        ..*overview
+    };
+    let (pixels, _) = render_with_sky(&looking_up, &map, &texture, &sky, DEFAULT_METHOD);
+    assert!(pixels[..SCREEN_W].iter().all(|p| p.b == 0));
+}
+
+#[test]
+fn fog_models() {
+    let (map, texture) = synthetic_terrain();
+    let overview = &CASES[0];
+    let sky = RGB8::new(80, 120, 250);
+    // Mean distance of the channels to a color
+    let distance = |pixels: &[RGB8], color: RGB8| {
+        pixels
+            .iter()
+            .map(|p| {
+                p.r.abs_diff(color.r) as u32
+                    + p.g.abs_diff(color.g) as u32
+                    + p.b.abs_diff(color.b) as u32
+            })
+            .sum::<u32>() as f32
+            / pixels.len() as f32
+    };
+    let linear = render(overview, &map, &texture, DEFAULT_METHOD);
+    let with_model = |fog_model, fog_density| {
+        let method = Method {
+            fog_model,
+            fog_density,
+            ..DEFAULT_METHOD
+        };
+        render(overview, &map, &texture, method)
+    };
+
+    // Exponential fog thickens right after fog_start, and the squared one close to distance_max
+    // when it is not too dense
+    let exponential = with_model(FogModel::Exponential, 0.02);
+    let squared = with_model(FogModel::ExponentialSquared, 0.005);
+    assert!(distance(&exponential, sky) < distance(&linear, sky));
+    assert!(distance(&squared, sky) > distance(&linear, sky));
+
+    // The valleys fill with white fog, the sky stays as it was
+    let white = RGB8::new(255, 255, 255);
+    let method = Method {
+        height_fog: Some(HeightFogConfig {
+            height: 100.,
+            density: 0.02,
+            color: [255, 255, 255],
+        }),
+        ..DEFAULT_METHOD
+    };
+    let foggy = render(overview, &map, &texture, method);
+    assert!(distance(&foggy, white) < distance(&linear, white));
+    for (f, l) in foggy.iter().zip(&linear) {
+        assert!(*l != sky || f == l);
+    }
+    let integer = render(
+        overview,
+        &map,
+        &texture,
+        Method {
+            integer_only: true,
+            ..method
+        },
+    );
+    assert!(distance(&integer, white) < distance(&linear, white));
+}